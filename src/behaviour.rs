@@ -0,0 +1,123 @@
+use constants::*;
+use faction::Relation;
+use object::*;
+use rand::{self, Rng};
+use serde_json;
+use spells::Spell;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Behaviours live in a content file, so a new monster can mix and match
+/// the actions below without any new code.
+const BEHAVIOURS: &str = include_str!("../data/behaviours.json");
+
+/// One line of a monster's behaviour. Each turn the rules are tried from the
+/// highest priority down, and the first one whose conditions all hold and
+/// whose action actually happens ends the turn.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+  pub priority: i32,
+  #[serde(default)]
+  pub when: Vec<Condition>,
+  pub action: Action,
+}
+
+/// Conditions about "the enemy" refer to the nearest hostile creature the
+/// monster can see, and are false when there is none.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Condition {
+  EnemyVisible,
+  /// The enemy is at most this far away
+  EnemyWithin(f32),
+  /// The enemy is further away than this
+  EnemyBeyond(f32),
+  /// The monster has less than this percentage of its health left
+  HealthBelow(i32),
+  /// There are fewer than this many allies within torch range
+  AlliesFewerThan(usize),
+  /// Holds this percentage of the time
+  Chance(u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Action {
+  /// Attack the enemy if it's adjacent
+  Melee,
+  /// Use the monster's ranged attack on the enemy if there's a line of fire
+  Shoot,
+  /// Move towards the enemy
+  Approach,
+  /// Step away from the enemy, unless cornered
+  Retreat,
+  /// Run until there's no enemy in sight
+  Flee,
+  /// Cast a spell the monster knows, once it's off cooldown and worth casting
+  Cast(Spell),
+  /// Call up monsters of the given kind on the free tiles around the monster
+  Summon { kind: String, count: i32 },
+  /// Follow the player's trail, or go about the monster's idle routine
+  Idle,
+}
+
+impl Condition {
+  pub fn holds(
+    self,
+    monster_id: usize,
+    enemy_id: Option<usize>,
+    game: &Game,
+    objects: &[Object],
+  ) -> bool {
+    let monster = &objects[monster_id];
+    let enemy_distance = enemy_id.map(|id| monster.distance_to(&objects[id]));
+    match self {
+      Condition::EnemyVisible => enemy_id.is_some(),
+      Condition::EnemyWithin(distance) => enemy_distance.is_some_and(|d| d <= distance),
+      Condition::EnemyBeyond(distance) => enemy_distance.is_some_and(|d| d > distance),
+      Condition::HealthBelow(percent) => monster
+        .fighter
+        .is_some_and(|f| f.hp * 100 < percent * monster.max_hp(game)),
+      Condition::AlliesFewerThan(count) => {
+        let allies = objects
+          .iter()
+          .enumerate()
+          .filter(|&(id, object)| {
+            id != monster_id
+              && object.alive
+              && monster.relation_to(object) == Relation::Allied
+              && monster.distance_to(object) <= TORCH_RADIUS as f32
+          })
+          .count();
+        allies < count
+      }
+      Condition::Chance(percent) => rand::thread_rng().gen_range(0, 100) < percent,
+    }
+  }
+}
+
+thread_local! {
+  /// The content file is only parsed the first time a monster needs it.
+  static PARSED: HashMap<String, Rc<Vec<Rule>>> = parse();
+}
+
+fn parse() -> HashMap<String, Rc<Vec<Rule>>> {
+  let behaviours: HashMap<String, Vec<Rule>> =
+    serde_json::from_str(BEHAVIOURS).expect("behaviours.json is malformed");
+  behaviours
+    .into_iter()
+    .map(|(name, mut rules)| {
+      rules.sort_by_key(|rule| Reverse(rule.priority));
+      (name, Rc::new(rules))
+    })
+    .collect()
+}
+
+/// The rules of the named behaviour, highest priority first.
+pub fn load(name: &str) -> Rc<Vec<Rule>> {
+  PARSED.with(|behaviours| {
+    behaviours
+      .get(name)
+      .cloned()
+      .unwrap_or_else(|| panic!("No behaviour called {}", name))
+  })
+}
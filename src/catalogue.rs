@@ -0,0 +1,87 @@
+use identify::Disguise;
+use object::*;
+use rand::Rng;
+use serde_json;
+use std::rc::Rc;
+use tcod::colors::Color;
+use utils::{from_dungeon_level, Transition};
+
+/// Items live in a content file, so a new sword or potion needs nothing more
+/// than a new entry there.
+const ITEMS: &str = include_str!("../data/items.json");
+
+/// Everything that goes into making an item of one kind.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ItemDef {
+  pub name: String,
+  pub glyph: char,
+  pub color: Color,
+  /// How often the item lies around on each level. Items with no chances
+  /// only turn up as loot or starting gear.
+  #[serde(default)]
+  pub spawn: Vec<Transition>,
+  #[serde(rename = "use")]
+  pub on_use: Item,
+  pub equipment: Option<Equipment>,
+  pub ammo: Option<AmmoKind>,
+  /// Items that stack take up one inventory slot however many there are
+  pub stack: Option<Pile>,
+  /// How many charges wands and staves are found with
+  pub charges: Option<Pile>,
+  /// Items with a disguise have to be identified before the player knows
+  /// what they are
+  pub disguise: Option<Disguise>,
+}
+
+/// A range of how many of something an item comes with.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pile {
+  pub min: i32,
+  pub max: i32,
+}
+
+impl ItemDef {
+  pub fn spawn_weight(&self, level: u32) -> u32 {
+    from_dungeon_level(&self.spawn, level)
+  }
+
+  /// A new item of this kind. Stack sizes and charges are rolled with `rng`.
+  pub fn create<R: Rng>(&self, x: i32, y: i32, rng: &mut R) -> Object {
+    let mut object = Object::new(x, y, self.glyph, &self.name, self.color, false);
+    object.item = Some(self.on_use);
+    object.kind = Some(self.name.clone());
+    object.equipment = self.equipment;
+    object.ammo = self.ammo;
+    if let Some(pile) = self.stack {
+      object.stack = Some(Stack {
+        singular: self.name.clone(),
+        count: 1,
+      });
+      object.set_count(rng.gen_range(pile.min, pile.max + 1));
+    }
+    if let Some(pile) = self.charges {
+      object.charges = Some(rng.gen_range(pile.min, pile.max + 1));
+    }
+    object
+  }
+}
+
+thread_local! {
+  /// The content file is only parsed the first time an item is needed.
+  static PARSED: Rc<Vec<ItemDef>> =
+    Rc::new(serde_json::from_str(ITEMS).expect("items.json is malformed"));
+}
+
+/// Every item in the catalogue.
+pub fn load() -> Rc<Vec<ItemDef>> {
+  PARSED.with(|catalogue| catalogue.clone())
+}
+
+/// A new item of the named kind.
+pub fn new_item<R: Rng>(name: &str, x: i32, y: i32, rng: &mut R) -> Object {
+  load()
+    .iter()
+    .find(|def| def.name == name)
+    .unwrap_or_else(|| panic!("No item called {}", name))
+    .create(x, y, rng)
+}
@@ -7,6 +7,9 @@ pub const MAP_WIDTH: i32 = 80;
 pub const MAP_HEIGHT: i32 = 43;
 pub const LIMIT_FPS: i32 = 20;
 
+/// Bumped whenever saved games stop being readable by the new code
+pub const SAVE_VERSION: u64 = 2;
+
 pub const ROOM_MAX_SIZE: i32 = 10;
 pub const ROOM_MIN_SIZE: i32 = 6;
 pub const MAX_ROOMS: i32 = 30;
@@ -21,13 +24,48 @@ pub const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
 pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 
 pub const PLAYER: usize = 0;
-pub const HEAL_AMOUNT: i32 = 40;
+/// Catalogue name of the weapon the player starts out with
+pub const STARTING_WEAPON: &str = "dagger";
 pub const LIGHTNING_DAMAGE: i32 = 40;
 pub const LIGHTNING_RANGE: i32 = 5;
-pub const CONFUSE_RANGE: i32 = 8;
-pub const CONFUSE_NUM_TURNS: i32 = 10;
 pub const FIREBALL_RADIUS: i32 = 3;
 pub const FIREBALL_DAMAGE: i32 = 25;
+pub const POISON_NUM_TURNS: i32 = 5;
+pub const POISON_DAMAGE: i32 = 1;
+pub const MONSTER_SPELL_RANGE: i32 = 6;
+pub const MONSTER_HEAL_AMOUNT: i32 = 15;
+pub const MONSTER_CONFUSE_NUM_TURNS: i32 = 5;
+pub const OCCUPIED_TILE_PATH_COST: i32 = 10;
+pub const ALLY_DEATH_MORALE_LOSS: i32 = 25;
+pub const ALLY_DEATH_FEAR_RADIUS: f32 = 8.0;
+pub const MORALE_RECOVERY: i32 = 5;
+pub const LEADER_DEATH_MORALE_LOSS: i32 = 40;
+pub const PACK_AMBUSH_DISTANCE: f32 = 4.0;
+pub const COMPANION_ENGAGE_RANGE: f32 = 6.0;
+pub const GUARD_STAIRS_DISTANCE: f32 = 6.0;
+pub const BASE_HIT_CHANCE: i32 = 70;
+/// How much each point of accuracy over the target's evasion adds to the
+/// chance to hit, in percent
+pub const HIT_CHANCE_PER_POINT: i32 = 5;
+pub const MIN_HIT_CHANCE: i32 = 5;
+pub const MAX_HIT_CHANCE: i32 = 95;
+/// Percentage of hits that are critical and roll double damage dice
+pub const CRITICAL_CHANCE: i32 = 5;
+/// How much each tile between a shooter and its target, past the first,
+/// takes off the chance to hit, in percent
+pub const RANGE_PENALTY_PER_TILE: i32 = 3;
+pub const THROW_RANGE: i32 = 6;
+/// How much harder it is to hit with the weapon hand while also wielding a
+/// weapon in the off hand, in percent
+pub const DUAL_WIELD_PENALTY: i32 = 10;
+pub const OFF_HAND_PENALTY: i32 = 25;
+pub const CORPSE_DECAY_TURNS: i32 = 150;
+/// How many attacks the combat breakdown screen remembers
+pub const ATTACK_RECORDS_KEPT: usize = 5;
+pub const COMBAT_SCREEN_WIDTH: i32 = 70;
+pub const EQUIPMENT_SCREEN_WIDTH: i32 = 70;
+/// How far from where it shatters a thrown potion still splashes
+pub const POTION_SPLASH_RADIUS: f32 = 1.5;
 pub const LEVEL_UP_BASE: i32 = 200;
 pub const LEVEL_UP_FACTOR: i32 = 150;
 
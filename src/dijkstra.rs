@@ -0,0 +1,151 @@
+use constants::*;
+use object::*;
+use pathfinding::movement_cost;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+const UNREACHABLE: i32 = i32::MAX;
+
+/// A distance field over the whole level. Every tile holds the cost of
+/// reaching it from the nearest source, so a creature approaches the sources
+/// by stepping to a lower neighbour. Unlike A*, one map answers the question
+/// for every creature on the level at once.
+pub struct DijkstraMap {
+  values: Vec<i32>,
+}
+
+impl DijkstraMap {
+  /// Spread out from `sources`, which all start at distance zero. `cost` gives
+  /// the price of entering a tile, or `None` if it can't be entered.
+  pub fn new<F>(sources: &[(i32, i32)], cost: F) -> Self
+  where
+    F: Fn(i32, i32) -> Option<i32>,
+  {
+    let seeds: Vec<_> = sources.iter().map(|&pos| (pos, 0)).collect();
+    DijkstraMap::from_seeds(&seeds, cost)
+  }
+
+  /// Like `new`, but every source starts at its own value.
+  pub fn from_seeds<F>(seeds: &[((i32, i32), i32)], cost: F) -> Self
+  where
+    F: Fn(i32, i32) -> Option<i32>,
+  {
+    let mut values = vec![UNREACHABLE; (MAP_WIDTH * MAP_HEIGHT) as usize];
+    let mut open = BinaryHeap::new();
+    for &(pos, value) in seeds {
+      if value < values[index(pos)] {
+        values[index(pos)] = value;
+        open.push(Reverse((value, pos)));
+      }
+    }
+
+    while let Some(Reverse((value, pos))) = open.pop() {
+      if value > values[index(pos)] {
+        continue;
+      }
+      for next in neighbours(pos) {
+        if let Some(step_cost) = cost(next.0, next.1) {
+          let next_value = value + step_cost;
+          if next_value < values[index(next)] {
+            values[index(next)] = next_value;
+            open.push(Reverse((next_value, next)));
+          }
+        }
+      }
+    }
+    DijkstraMap { values }
+  }
+
+  /// Distance to a creature, usually the player. Other creatures count as
+  /// obstacles that can be walked around, the same way A* treats them.
+  pub fn to_object(id: usize, map: &Map, objects: &[Object]) -> Self {
+    DijkstraMap::new(&[objects[id].pos()], |x, y| {
+      movement_cost(x, y, map, objects)
+    })
+  }
+
+  /// Distance to the nearest open tile the player hasn't seen yet.
+  pub fn to_unexplored(map: &Map) -> Self {
+    let mut unexplored = vec![];
+    for x in 0..MAP_WIDTH {
+      for y in 0..MAP_HEIGHT {
+        let tile = &map[x as usize][y as usize];
+        if !tile.explored && !tile.blocked {
+          unexplored.push((x, y));
+        }
+      }
+    }
+    DijkstraMap::new(&unexplored, |x, y| movement_cost(x, y, map, &[]))
+  }
+
+  /// Distance to the stairs down.
+  pub fn to_stairs(map: &Map, objects: &[Object]) -> Self {
+    let stairs: Vec<_> = objects
+      .iter()
+      .filter(|object| object.name == "stairs")
+      .map(|object| object.pos())
+      .collect();
+    DijkstraMap::new(&stairs, |x, y| movement_cost(x, y, map, &[]))
+  }
+
+  /// Turn an approach map into one for running away. Simply walking uphill
+  /// leads into the nearest dead end, so the values are inverted and scaled
+  /// past zero before being rescanned: a fleeing creature will happily pass
+  /// a little closer to the danger if that gets it somewhere more open.
+  pub fn safety<F>(&self, cost: F) -> Self
+  where
+    F: Fn(i32, i32) -> Option<i32>,
+  {
+    let mut seeds = vec![];
+    for x in 0..MAP_WIDTH {
+      for y in 0..MAP_HEIGHT {
+        if let Some(value) = self.value(x, y) {
+          seeds.push(((x, y), -value * 6 / 5));
+        }
+      }
+    }
+    DijkstraMap::from_seeds(&seeds, cost)
+  }
+
+  pub fn value(&self, x: i32, y: i32) -> Option<i32> {
+    match self.values[index((x, y))] {
+      UNREACHABLE => None,
+      value => Some(value),
+    }
+  }
+
+  /// The enterable neighbour with the lowest value, if it is lower than the
+  /// value at `(x, y)`.
+  pub fn downhill<F>(&self, x: i32, y: i32, can_enter: F) -> Option<(i32, i32)>
+  where
+    F: Fn(i32, i32) -> bool,
+  {
+    let mut best = None;
+    let mut best_value = self.values[index((x, y))];
+    for (nx, ny) in neighbours((x, y)) {
+      let value = self.values[index((nx, ny))];
+      if value < best_value && can_enter(nx, ny) {
+        best = Some((nx, ny));
+        best_value = value;
+      }
+    }
+    best
+  }
+}
+
+fn index((x, y): (i32, i32)) -> usize {
+  (x * MAP_HEIGHT + y) as usize
+}
+
+fn neighbours((x, y): (i32, i32)) -> Vec<(i32, i32)> {
+  let mut result = vec![];
+  for dx in -1..2 {
+    for dy in -1..2 {
+      let (nx, ny) = (x + dx, y + dy);
+      if (dx, dy) != (0, 0) && nx >= 0 && ny >= 0 && nx < MAP_WIDTH && ny < MAP_HEIGHT {
+        result.push((nx, ny));
+      }
+    }
+  }
+  result
+}
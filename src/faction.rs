@@ -0,0 +1,46 @@
+use self::Faction::*;
+use self::Relation::*;
+
+/// The side a creature fights for. Creatures only attack the factions they
+/// are hostile towards.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Faction {
+  /// The player and their companions
+  Player,
+  Orcs,
+  Goblins,
+  Wolves,
+  Trolls,
+  Cultists,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Relation {
+  Hostile,
+  Neutral,
+  Allied,
+}
+
+/// How the factions feel about each other. The table is symmetric, pairs
+/// that aren't listed are neutral, and every faction is allied with itself.
+const RELATIONS: &[(Faction, Faction, Relation)] = &[
+  (Player, Orcs, Hostile),
+  (Player, Goblins, Hostile),
+  (Player, Wolves, Hostile),
+  (Player, Trolls, Hostile),
+  (Player, Cultists, Hostile),
+  (Orcs, Goblins, Hostile),
+  (Orcs, Trolls, Allied),
+  (Goblins, Wolves, Allied),
+  (Goblins, Trolls, Hostile),
+];
+
+pub fn relation(a: Faction, b: Faction) -> Relation {
+  if a == b {
+    return Allied;
+  }
+  RELATIONS
+    .iter()
+    .find(|&&(x, y, _)| (x, y) == (a, b) || (x, y) == (b, a))
+    .map_or(Neutral, |&(_, _, relation)| relation)
+}
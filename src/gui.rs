@@ -128,7 +128,9 @@ pub fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Op
     inventory
       .iter()
       .map(|item| match item.equipment {
-        Some(equipment) if equipment.equipped => format!("{} (on {})", item.name, equipment.slot),
+        Some(equipment) if equipment.equipped => {
+          format!("{} (on {})", item.name, equipment.place())
+        }
         _ => item.name.clone(),
       })
       .collect()
@@ -143,19 +145,129 @@ pub fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Op
   }
 }
 
-pub fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+/// The player's equipment slot by slot, with what each item does. Returns
+/// the inventory index of the item in the slot the player picks, if any.
+pub fn equipment_menu(inventory: &[Object], root: &mut Root) -> Option<usize> {
+  let worn: Vec<_> = Slot::all()
+    .iter()
+    .map(|&slot| {
+      let item = inventory.iter().position(|item| {
+        item
+          .equipment
+          .is_some_and(|e| e.equipped && e.slots().contains(&slot))
+      });
+      (slot, item)
+    })
+    .collect();
+  let options: Vec<_> = worn
+    .iter()
+    .map(|&(slot, item)| {
+      let slot = format!("{}:", slot);
+      match item.map(|id| (&inventory[id].name, inventory[id].equipment.unwrap())) {
+        Some((name, equipment)) => {
+          let stats = equipment.stats();
+          if stats.is_empty() {
+            format!("{:<14}{}", slot, name)
+          } else {
+            format!("{:<14}{} ({})", slot, name, stats)
+          }
+        }
+        None => format!("{:<14}-", slot),
+      }
+    })
+    .collect();
+
+  let header = "Equipment\n\nPress the key next to a slot to take off what's in it, \
+                or any other to cancel.\n";
+  menu(header, &options, EQUIPMENT_SCREEN_WIDTH, root).and_then(|index| worn[index].1)
+}
+
+/// Take an item out of the inventory, unequipping it first if need be.
+pub fn take_from_inventory(inventory_id: usize, game: &mut Game) -> Object {
   let mut item = game.inventory.remove(inventory_id);
-  item.set_pos(objects[PLAYER].x, objects[PLAYER].y);
   if item.equipment.is_some() {
     item.dequip(&mut game.log);
   }
-  game
-    .log
-    .add(format!("You dropped a {}.", item.name), colors::YELLOW);
-  objects.push(item);
+  item
+}
+
+/// Drop an item at the player's feet. For a stack, the player is asked
+/// whether to drop just one or all of them.
+pub fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<Object>, root: &mut Root) {
+  let count = game.inventory[inventory_id].count();
+  let item = if count > 1 {
+    let all = format!("All {}", count);
+    match menu("Drop how many?\n", &["One", &all], INVENTORY_WIDTH, root) {
+      Some(0) => game.inventory[inventory_id].split_off(1),
+      Some(_) => take_from_inventory(inventory_id, game),
+      None => return,
+    }
+  } else {
+    take_from_inventory(inventory_id, game)
+  };
+  game.log.add(
+    format!("You dropped {}.", item.indefinite_name()),
+    colors::YELLOW,
+  );
+  let pos = objects[PLAYER].pos();
+  land(item, pos, objects);
 }
 
 pub fn msgbox(text: &str, width: i32, root: &mut Root) {
   let options: &[&str] = &[];
   menu(text, options, width, root);
 }
+
+/// Show how the last few attacks were worked out, and how much damage the
+/// player has dealt and taken on each level.
+pub fn combat_breakdown(game: &Game, root: &mut Root) {
+  let mut text = String::from("Combat breakdown\n\n");
+  if game.attacks.is_empty() {
+    text.push_str("Nobody has attacked anyone yet.\n");
+  }
+  for record in &game.attacks {
+    text.push_str(&format!(
+      "Turn {}: {} {} {}\n",
+      record.turn, record.attacker, record.verb, record.target
+    ));
+    text.push_str(&format!(
+      "  To hit: rolled {} against {}% (accuracy {} against evasion {}, -{}% penalty)\n",
+      record.to_hit, record.hit_chance, record.accuracy, record.evasion, record.penalty
+    ));
+    if !record.hit {
+      text.push_str("  Missed\n");
+      continue;
+    }
+    if record.blocked {
+      text.push_str("  Blocked by a shield\n");
+      continue;
+    }
+    text.push_str(&format!(
+      "  Damage: {} rolled {}{}, plus power {} (base {}, equipment {:+}, effects {:+})\n",
+      record.dice,
+      record.dice_roll,
+      if record.critical {
+        " on a critical"
+      } else {
+        ""
+      },
+      record.base_power + record.equipment_power + record.effect_power,
+      record.base_power,
+      record.equipment_power,
+      record.effect_power
+    ));
+    text.push_str(&format!(
+      "  Armour: defense {} absorbed {}, {}% {} resistance, {} damage dealt\n",
+      record.defense, record.absorbed, record.resistance, record.damage_type, record.damage
+    ));
+  }
+
+  text.push_str("\nDamage by level\n");
+  for stats in &game.damage_stats {
+    text.push_str(&format!(
+      "  Level {}: {} dealt, {} taken\n",
+      stats.level, stats.dealt, stats.taken
+    ));
+  }
+  msgbox(&text, COMBAT_SCREEN_WIDTH, root);
+}
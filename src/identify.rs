@@ -0,0 +1,192 @@
+use catalogue::ItemDef;
+use gui::*;
+use object::*;
+use rand::Rng;
+use std::collections::HashMap;
+use tcod::colors::{self, Color};
+use utils::plural;
+
+const POTION_LOOKS: &[(&str, Color)] = &[
+  ("murky", colors::DARKER_SEPIA),
+  ("bubbling", colors::LIGHT_GREEN),
+  ("fizzy", colors::LIGHT_YELLOW),
+  ("smoky", colors::GREY),
+  ("golden", colors::GOLD),
+  ("crimson", colors::CRIMSON),
+  ("violet", colors::VIOLET),
+  ("cloudy", colors::LIGHTER_GREY),
+  ("oily", colors::DARK_AMBER),
+  ("glowing", colors::LIGHT_AZURE),
+  ("milky", colors::WHITE),
+  ("inky", colors::DARKER_BLUE),
+  ("pink", colors::PINK),
+  ("emerald", colors::DARK_GREEN),
+];
+
+const WAND_LOOKS: &[(&str, Color)] = &[
+  ("oak", colors::DARK_SEPIA),
+  ("bone", colors::LIGHTEST_GREY),
+  ("iron", colors::GREY),
+  ("glass", colors::LIGHT_CYAN),
+  ("copper", colors::DARK_ORANGE),
+  ("ebony", colors::DARKER_GREY),
+  ("silver", colors::SILVER),
+  ("crystal", colors::LIGHTEST_AZURE),
+];
+
+const STAFF_LOOKS: &[(&str, Color)] = &[
+  ("gnarled", colors::DARK_SEPIA),
+  ("runed", colors::LIGHT_VIOLET),
+  ("twisted", colors::DARKER_SEPIA),
+  ("ashen", colors::LIGHTER_GREY),
+  ("mossy", colors::DARK_CHARTREUSE),
+];
+
+const SCROLL_SYLLABLES: &[&str] = &[
+  "ab", "ra", "ka", "dab", "xyz", "zy", "fo", "ob", "lum", "nex", "kra", "vor", "tel", "mun",
+  "ish", "ee", "zor", "gal", "po", "thu",
+];
+
+/// What an item looks like before the player knows what it is.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Disguise {
+  Potion,
+  Scroll,
+  Wand,
+  Staff,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Appearance {
+  pub name: String,
+  pub color: Color,
+}
+
+/// Which kinds of potion, scroll, wand and staff the player has figured
+/// out, and what the rest look like until then. Each game shuffles the
+/// appearances anew.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Identification {
+  /// How the items of each kind look, by catalogue name
+  appearances: HashMap<String, Appearance>,
+  known: Vec<String>,
+}
+
+impl Identification {
+  /// Hand out a different appearance to every disguised item in the
+  /// catalogue. If there are more potions than potion looks, or wands or
+  /// staves, the extra ones are simply known from the start.
+  pub fn new<R: Rng>(catalogue: &[ItemDef], rng: &mut R) -> Self {
+    let mut potion_looks = POTION_LOOKS.to_vec();
+    let mut wand_looks = WAND_LOOKS.to_vec();
+    let mut staff_looks = STAFF_LOOKS.to_vec();
+    rng.shuffle(&mut potion_looks);
+    rng.shuffle(&mut wand_looks);
+    rng.shuffle(&mut staff_looks);
+    let mut labels = vec![];
+
+    let mut appearances = HashMap::new();
+    for def in catalogue {
+      let appearance = match def.disguise {
+        Some(Disguise::Potion) => potion_looks.pop().map(|(look, color)| Appearance {
+          name: format!("{} potion", look),
+          color,
+        }),
+        Some(Disguise::Wand) => wand_looks.pop().map(|(look, color)| Appearance {
+          name: format!("{} wand", look),
+          color,
+        }),
+        Some(Disguise::Staff) => staff_looks.pop().map(|(look, color)| Appearance {
+          name: format!("{} staff", look),
+          color,
+        }),
+        Some(Disguise::Scroll) => {
+          let label = loop {
+            let label: String = (0..rng.gen_range(2, 4))
+              .map(|_| *rng.choose(SCROLL_SYLLABLES).unwrap())
+              .collect();
+            if !labels.contains(&label) {
+              break label;
+            }
+          };
+          labels.push(label.clone());
+          Some(Appearance {
+            name: format!("scroll labelled {}", label.to_uppercase()),
+            color: def.color,
+          })
+        }
+        None => None,
+      };
+      if let Some(appearance) = appearance {
+        appearances.insert(def.name.clone(), appearance);
+      }
+    }
+    Identification {
+      appearances,
+      known: vec![],
+    }
+  }
+
+  pub fn is_known(&self, kind: &str) -> bool {
+    !self.appearances.contains_key(kind) || self.known.iter().any(|known| known == kind)
+  }
+
+  /// Make the item look the way items of its kind do in this game, and give
+  /// it its real name only if the player knows it. Known wands and staves
+  /// also show how many charges they have left.
+  pub fn disguise(&self, object: &mut Object) {
+    let kind = match object.kind.clone() {
+      Some(kind) => kind,
+      None => return,
+    };
+    let known = self.is_known(&kind);
+    let appearance = self.appearances.get(&kind);
+    if let Some(appearance) = appearance {
+      object.color = appearance.color;
+    }
+    let mut name = match appearance {
+      Some(appearance) if !known => appearance.name.clone(),
+      _ => kind,
+    };
+    match object.charges {
+      Some(1) if known => name = format!("{} (1 charge)", name),
+      Some(charges) if known => name = format!("{} ({} charges)", name, charges),
+      _ => {}
+    }
+    match object.stack {
+      Some(ref mut stack) => stack.singular = name,
+      None => object.name = name,
+    }
+    let count = object.count();
+    object.set_count(count);
+  }
+
+  pub fn disguise_all(&self, objects: &mut [Object]) {
+    for object in objects {
+      self.disguise(object);
+    }
+  }
+}
+
+/// Learn what items of this kind are for the rest of the game, and rename
+/// every one of them on the level and in the inventory.
+pub fn identify(kind: &str, objects: &mut [Object], game: &mut Game) {
+  if game.identification.is_known(kind) {
+    return;
+  }
+  game.identification.known.push(kind.into());
+  game.log.add(
+    format!(
+      "You now know that {} are {}.",
+      plural(&game.identification.appearances[kind].name),
+      plural(kind)
+    ),
+    colors::LIGHT_CYAN,
+  );
+  let identification = &game.identification;
+  for object in objects.iter_mut().chain(game.inventory.iter_mut()) {
+    if object.kind.as_ref().is_some_and(|k| k == kind) {
+      identification.disguise(object);
+    }
+  }
+}
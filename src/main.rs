@@ -5,6 +5,7 @@ extern crate tcod;
 extern crate serde_derive;
 extern crate serde_json;
 
+use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
 use rand::Rng;
 
 use std::cmp;
@@ -18,28 +19,32 @@ use tcod::image::*;
 use tcod::input::{self, Event};
 use tcod::map::Map as FovMap;
 
+mod behaviour;
+mod catalogue;
 mod constants;
+mod dijkstra;
+mod faction;
 mod gui;
+mod identify;
 mod object;
+mod pathfinding;
+mod projectile;
+mod rng;
+mod spells;
+mod status;
 mod utils;
+use behaviour::Action;
 use constants::*;
+use dijkstra::DijkstraMap;
+use faction::{Faction, Relation};
 use gui::*;
+use identify::Identification;
 use object::*;
+use rng::GameRng;
+use spells::{KnownSpell, Spell};
+use status::Effect;
 use utils::*;
 
-struct Transition {
-    level: u32,
-    value: u32,
-}
-
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
-    table
-        .iter()
-        .rev()
-        .find(|transition| level >= transition.level)
-        .map_or(0, |transition| transition.value)
-}
-
 #[derive(Clone, Copy, Debug, PartialEq)]
 enum PlayerAction {
     TookTurn,
@@ -116,69 +121,912 @@ fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
         .any(|object| object.blocks && object.pos() == (x, y))
 }
 
-fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &FovMap) {
+fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut Vec<Object>, fov_map: &FovMap) {
     use Ai::*;
+    if objects[monster_id].has_effect(Effect::Paralysis) {
+        return;
+    }
+    if objects[monster_id].has_effect(Effect::Confusion) {
+        // Too busy stumbling around to think, or even to be afraid
+        stumble(monster_id, game, objects, fov_map);
+        return;
+    }
     if let Some(ai) = objects[monster_id].ai.take() {
+        let ai = check_morale(monster_id, game, objects, fov_map, ai);
         let new_ai = match ai {
             Basic => ai_basic(monster_id, game, objects, fov_map),
-            Confused {
-                previous_ai,
-                num_turns,
-            } => ai_confused(monster_id, game, objects, previous_ai, num_turns),
+            Ranged => ai_ranged(monster_id, game, objects, fov_map),
+            Pack => ai_pack(monster_id, game, objects, fov_map),
+            Behaviour { name } => ai_behaviour(monster_id, game, objects, fov_map, name),
+            Companion { order } => ai_companion(monster_id, game, objects, fov_map, order),
+            Fleeing { previous_ai } => ai_fleeing(monster_id, game, objects, fov_map, previous_ai),
         };
         objects[monster_id].ai = Some(new_ai);
     }
 }
 
+/// Make a monster whose courage has broken start fleeing.
+fn check_morale(
+    monster_id: usize,
+    game: &mut Game,
+    objects: &[Object],
+    fov_map: &FovMap,
+    ai: Ai,
+) -> Ai {
+    let monster = &objects[monster_id];
+    let broken = monster.morale.is_some_and(|morale| morale.is_broken());
+    match ai {
+        Ai::Fleeing { .. } => ai,
+        _ if broken => {
+            if fov_map.is_in_fov(monster.x, monster.y) {
+                game.log.add(
+                    format!("The {} flees in terror!", monster.name),
+                    colors::LIGHT_ORANGE,
+                );
+            }
+            Ai::Fleeing {
+                previous_ai: Box::new(ai),
+            }
+        }
+        _ => ai,
+    }
+}
+
+/// Monsters lose courage when they see one of their allies die nearby.
+fn spread_fear(game: &mut Game, objects: &mut [Object]) {
+    for ((x, y), dead_faction) in game.recent_deaths.drain(..) {
+        for object in objects.iter_mut() {
+            let allied = object.faction.is_some_and(|faction| {
+                faction::relation(faction, dead_faction) == Relation::Allied
+            });
+            let saw_it = object.distance(x, y) <= ALLY_DEATH_FEAR_RADIUS
+                && can_see(object.pos(), (x, y), &game.map);
+            if object.alive && allied && saw_it {
+                if let Some(morale) = object.morale.as_mut() {
+                    morale.lose(ALLY_DEATH_MORALE_LOSS);
+                }
+            }
+        }
+    }
+}
+
+/// Whether nothing blocks the view between two tiles.
+fn can_see(from: (i32, i32), to: (i32, i32), map: &Map) -> bool {
+    line(from, to)
+        .into_iter()
+        .all(|(x, y)| (x, y) == to || !map[x as usize][y as usize].block_sight)
+}
+
 fn ai_basic(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &FovMap) -> Ai {
+    let target_id =
+        nearest_visible_hostile(monster_id, TORCH_RADIUS as f32, game, objects, fov_map);
+    match target_id {
+        Some(target_id) => engage(monster_id, target_id, game, objects, fov_map),
+        None => hunt_or_idle(monster_id, game, objects),
+    }
+    Ai::Basic
+}
+
+/// With no enemy in sight, follow the player's trail while it's fresh, and
+/// otherwise get on with whatever the monster does when idle.
+fn hunt_or_idle(monster_id: usize, game: &Game, objects: &mut [Object]) {
+    if let Some((x, y)) = objects[monster_id].last_seen_player {
+        if !has_reached(monster_id, (x, y), game, objects) {
+            move_astar(monster_id, x, y, &game.map, objects);
+            return;
+        }
+        objects[monster_id].last_seen_player = None;
+    }
+    idle(monster_id, game, objects);
+}
+
+/// Whether the monster has got as close to the spot as it can: standing on
+/// it, or next to it while someone else is in the way.
+fn has_reached(monster_id: usize, (x, y): (i32, i32), game: &Game, objects: &[Object]) -> bool {
+    objects[monster_id].pos() == (x, y)
+        || (objects[monster_id].distance(x, y) < 2.0 && is_blocked(x, y, &game.map, objects))
+}
+
+/// Take a step of the monster's idle routine.
+fn idle(monster_id: usize, game: &Game, objects: &mut [Object]) {
     let (monster_x, monster_y) = objects[monster_id].pos();
-    if fov_map.is_in_fov(monster_x, monster_y) {
-        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
+    let reached = |goal| has_reached(monster_id, goal, game, objects);
+    let goal = match objects[monster_id].idle {
+        Some(Idle::Wander { goal }) => {
+            let goal = match goal {
+                Some(goal) if !reached(goal) => Some(goal),
+                _ => rand::thread_rng().choose(&game.rooms).cloned(),
+            };
+            objects[monster_id].idle = Some(Idle::Wander { goal });
+            goal
+        }
+        Some(Idle::Patrol { ref route, next }) if !route.is_empty() => {
+            let next = if reached(route[next]) {
+                (next + 1) % route.len()
+            } else {
+                next
+            };
+            let goal = route[next];
+            objects[monster_id].idle = Some(Idle::Patrol {
+                route: route.clone(),
+                next,
+            });
+            Some(goal)
+        }
+        Some(Idle::Guard { post }) if post != (monster_x, monster_y) => Some(post),
+        _ => None,
+    };
+    if let Some((x, y)) = goal {
+        move_astar(monster_id, x, y, &game.map, objects);
+    }
+}
+
+fn ai_ranged(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &FovMap) -> Ai {
+    let ranged = match objects[monster_id].ranged {
+        Some(ranged) => ranged,
+        None => return ai_basic(monster_id, game, objects, fov_map),
+    };
+    let target_id =
+        match nearest_visible_hostile(monster_id, TORCH_RADIUS as f32, game, objects, fov_map) {
+            Some(target_id) => target_id,
+            None => {
+                hunt_or_idle(monster_id, game, objects);
+                return Ai::Ranged;
+            }
+        };
+
+    let monster_pos = objects[monster_id].pos();
+    let distance = objects[monster_id].distance_to(&objects[target_id]);
+    let line_of_fire = distance <= ranged.range as f32
+        && projectile::has_line_of_fire(monster_pos, target_id, ranged.range, &game.map, objects);
+
+    // Back off when the target gets too close, and only stand and fight when
+    // there's nowhere left to go.
+    if distance < ranged.preferred_distance as f32
+        && flee_step(monster_id, target_id, game, objects)
+    {
+        return Ai::Ranged;
+    }
+
+    if distance < 2.0 {
+        melee(monster_id, target_id, objects, game, fov_map);
+    } else if line_of_fire {
+        let strike = Strike {
+            verb: ranged.missile.verb(),
+            dice: objects[monster_id].damage_dice(game),
+            damage_type: ranged.missile.damage_type(),
+            penalty: projectile::range_penalty(distance),
+        };
+        let witnessed = witnesses(monster_id, target_id, objects, fov_map);
+        let (monster, target) = mut_two(monster_id, target_id, objects);
+        let blow = monster.hit(target, strike, witnessed, game);
+        settle_blow(monster_id, target_id, blow, objects, game);
+    } else {
+        let (target_x, target_y) = objects[target_id].pos();
+        move_astar(monster_id, target_x, target_y, &game.map, objects);
+    }
+    Ai::Ranged
+}
+
+fn ai_behaviour(
+    monster_id: usize,
+    game: &mut Game,
+    objects: &mut Vec<Object>,
+    fov_map: &FovMap,
+    name: String,
+) -> Ai {
+    for known in objects[monster_id].spells.iter_mut() {
+        if known.turns_left > 0 {
+            known.turns_left -= 1;
+        }
+    }
+
+    let enemy_id = nearest_visible_hostile(monster_id, TORCH_RADIUS as f32, game, objects, fov_map);
+    let rules = behaviour::load(&name);
+    for rule in rules.iter() {
+        let applies = rule
+            .when
+            .iter()
+            .all(|condition| condition.holds(monster_id, enemy_id, game, objects));
+        if !applies {
+            continue;
+        }
+        if rule.action == Action::Flee {
+            let monster = &objects[monster_id];
+            if fov_map.is_in_fov(monster.x, monster.y) {
+                game.log.add(
+                    format!("The {} turns and runs!", monster.name),
+                    colors::LIGHT_ORANGE,
+                );
+            }
+            return Ai::Fleeing {
+                previous_ai: Box::new(Ai::Behaviour { name }),
+            };
+        }
+        if perform(&rule.action, monster_id, enemy_id, game, objects, fov_map) {
+            break;
+        }
+    }
+    Ai::Behaviour { name }
+}
+
+/// Carry out a behaviour action. Everything but idling needs an enemy to act
+/// on. Returns whether the action happened.
+fn perform(
+    action: &Action,
+    monster_id: usize,
+    enemy_id: Option<usize>,
+    game: &mut Game,
+    objects: &mut Vec<Object>,
+    fov_map: &FovMap,
+) -> bool {
+    let enemy_id = match (action, enemy_id) {
+        (&Action::Idle, _) => {
+            hunt_or_idle(monster_id, game, objects);
+            return true;
+        }
+        (_, Some(enemy_id)) => enemy_id,
+        (_, None) => return false,
+    };
+    match *action {
+        Action::Melee => {
+            if objects[monster_id].distance_to(&objects[enemy_id]) < 2.0 {
+                melee(monster_id, enemy_id, objects, game, fov_map);
+                true
+            } else {
+                false
+            }
+        }
+        Action::Shoot => match objects[monster_id].ranged {
+            Some(ranged) if in_spell_range(monster_id, enemy_id, ranged.range, game, objects) => {
+                let distance = objects[monster_id].distance_to(&objects[enemy_id]);
+                let strike = Strike {
+                    verb: ranged.missile.verb(),
+                    dice: objects[monster_id].damage_dice(game),
+                    damage_type: ranged.missile.damage_type(),
+                    penalty: projectile::range_penalty(distance),
+                };
+                let witnessed = witnesses(monster_id, enemy_id, objects, fov_map);
+                let (monster, enemy) = mut_two(monster_id, enemy_id, objects);
+                let blow = monster.hit(enemy, strike, witnessed, game);
+                settle_blow(monster_id, enemy_id, blow, objects, game);
+                true
+            }
+            _ => false,
+        },
+        Action::Approach => {
+            let (enemy_x, enemy_y) = objects[enemy_id].pos();
+            move_astar(monster_id, enemy_x, enemy_y, &game.map, objects);
+            true
+        }
+        Action::Retreat => flee_step(monster_id, enemy_id, game, objects),
+        Action::Cast(spell) => {
+            let index = objects[monster_id]
+                .spells
+                .iter()
+                .position(|known| known.spell == spell && known.turns_left == 0);
+            match index {
+                Some(index) if monster_cast(monster_id, enemy_id, spell, game, objects) => {
+                    let known = &mut objects[monster_id].spells[index];
+                    known.turns_left = known.cooldown;
+                    true
+                }
+                _ => false,
+            }
+        }
+        Action::Summon { ref kind, count } => {
+            summon(monster_id, kind, count, game, objects, fov_map)
+        }
+        Action::Flee | Action::Idle => false,
+    }
+}
+
+/// Call up monsters of the given kind on the free tiles around the summoner.
+/// Kinds that don't exist, and uniques, can't be summoned. Returns whether
+/// any appeared.
+fn summon(
+    summoner_id: usize,
+    kind: &str,
+    count: i32,
+    game: &mut Game,
+    objects: &mut Vec<Object>,
+    fov_map: &FovMap,
+) -> bool {
+    if is_unique(kind) {
+        return false;
+    }
+    let (summoner_x, summoner_y) = objects[summoner_id].pos();
+    let mut summoned = 0;
+    for dx in -1..2 {
+        for dy in -1..2 {
+            let (x, y) = (summoner_x + dx, summoner_y + dy);
+            if summoned < count && !is_blocked(x, y, &game.map, objects) {
+                let mut monster = match new_monster(kind, x, y) {
+                    Some(monster) => monster,
+                    None => return false,
+                };
+                monster.faction = objects[summoner_id].faction;
+                // Summoned monsters are worth nothing, or they could be farmed
+                if let Some(fighter) = monster.fighter.as_mut() {
+                    fighter.xp = 0;
+                }
+                monster.loot = None;
+                objects.push(monster);
+                summoned += 1;
+            }
+        }
+    }
+    if summoned > 0 && fov_map.is_in_fov(summoner_x, summoner_y) {
+        game.log.add(
+            format!(
+                "The {} calls forth {} {}!",
+                objects[summoner_id].name,
+                summoned,
+                if summoned == 1 {
+                    kind.to_string()
+                } else {
+                    format!("{}s", kind)
+                }
+            ),
+            colors::LIGHT_VIOLET,
+        );
+    }
+    summoned > 0
+}
+
+/// Cast the spell if there's a good reason to. Offensive spells are aimed at
+/// `target_id`. Returns whether it was cast.
+fn monster_cast(
+    monster_id: usize,
+    target_id: usize,
+    spell: Spell,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> bool {
+    let name = objects[monster_id].name.clone();
+    match spell {
+        Spell::Heal => match most_hurt_ally(monster_id, game, objects) {
+            Some(ally_id) => {
+                game.log.add(
+                    format!(
+                        "The {} chants a prayer over the {}.",
+                        name, objects[ally_id].name
+                    ),
+                    colors::LIGHT_VIOLET,
+                );
+                spells::heal(ally_id, MONSTER_HEAL_AMOUNT, objects, game);
+                true
+            }
+            None => false,
+        },
+        Spell::Confuse => {
+            let in_range =
+                in_spell_range(monster_id, target_id, MONSTER_SPELL_RANGE, game, objects);
+            if in_range && !objects[target_id].has_effect(Effect::Confusion) {
+                let target_name = if target_id == PLAYER {
+                    "you".to_string()
+                } else {
+                    format!("the {}", objects[target_id].name)
+                };
+                game.log.add(
+                    format!(
+                        "The {} points at {} and mutters a curse.",
+                        name, target_name
+                    ),
+                    colors::LIGHT_VIOLET,
+                );
+                spells::confuse(target_id, MONSTER_CONFUSE_NUM_TURNS, objects, game);
+                true
+            } else {
+                false
+            }
+        }
+        Spell::Lightning => {
+            if in_spell_range(monster_id, target_id, LIGHTNING_RANGE, game, objects) {
+                game.log.add(
+                    format!("The {} calls down a bolt of lightning!", name),
+                    colors::LIGHT_BLUE,
+                );
+                let target = objects[target_id].pos();
+                spells::lightning(
+                    monster_id,
+                    target,
+                    LIGHTNING_DAMAGE,
+                    LIGHTNING_RANGE,
+                    objects,
+                    game,
+                );
+                true
+            } else {
+                false
+            }
+        }
+        Spell::Fireball => {
+            // Don't throw it if the caster or one of its allies would get burned
+            let (x, y) = objects[target_id].pos();
+            let area = projectile::blast_area((x, y), FIREBALL_RADIUS, &game.map);
+            let burns_friend = objects.iter().enumerate().any(|(id, object)| {
+                let friend = id == monster_id
+                    || (object.fighter.is_some()
+                        && objects[monster_id].relation_to(object) == Relation::Allied);
+                friend && area.contains(&object.pos())
+            });
+            let in_range =
+                in_spell_range(monster_id, target_id, MONSTER_SPELL_RANGE, game, objects);
+            if in_range && !burns_friend {
+                game.log
+                    .add(format!("The {} hurls a fireball!", name), colors::ORANGE);
+                spells::fireball(
+                    monster_id,
+                    (x, y),
+                    FIREBALL_DAMAGE,
+                    FIREBALL_RADIUS,
+                    objects,
+                    game,
+                );
+                true
+            } else {
+                false
+            }
+        }
+    }
+}
+
+fn in_spell_range(
+    caster_id: usize,
+    target_id: usize,
+    range: i32,
+    game: &Game,
+    objects: &[Object],
+) -> bool {
+    let caster_pos = objects[caster_id].pos();
+    objects[caster_id].distance_to(&objects[target_id]) <= range as f32
+        && projectile::has_line_of_fire(caster_pos, target_id, range, &game.map, objects)
+}
+
+/// The ally in reach that has lost the largest share of its health,
+/// as long as it's below half.
+fn most_hurt_ally(monster_id: usize, game: &Game, objects: &[Object]) -> Option<usize> {
+    let mut most_hurt = None;
+    let mut lowest_health = 0.5;
+    for (id, object) in objects.iter().enumerate() {
+        if id == monster_id || objects[monster_id].relation_to(object) != Relation::Allied {
+            continue;
+        }
+        if let Some(fighter) = object.fighter {
+            let health = fighter.hp as f32 / object.max_hp(game) as f32;
+            if health < lowest_health
+                && in_spell_range(monster_id, id, MONSTER_SPELL_RANGE, game, objects)
+            {
+                most_hurt = Some(id);
+                lowest_health = health;
+            }
+        }
+    }
+    most_hurt
+}
+
+fn ai_pack(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &FovMap) -> Ai {
+    let pack = match objects[monster_id].pack {
+        Some(pack) => pack,
+        None => return ai_basic(monster_id, game, objects, fov_map),
+    };
+    let leader_id = objects.iter().position(|object| {
+        object.alive
+            && object
+                .pack
+                .is_some_and(|other| other.pack_id == pack.pack_id && other.leader)
+    });
+    if pack.tactics == PackTactics::WarBand && leader_id.is_none() {
+        let monster = &mut objects[monster_id];
+        if fov_map.is_in_fov(monster.x, monster.y) {
+            game.log.add(
+                format!("The {} loses heart without its leader!", monster.name),
+                colors::LIGHT_ORANGE,
+            );
+        }
+        if let Some(morale) = monster.morale.as_mut() {
+            morale.lose(LEADER_DEATH_MORALE_LOSS);
+        }
+        monster.pack = None;
+        return Ai::Basic;
+    }
+
+    let monster_pos = objects[monster_id].pos();
+    if let Some(enemy_id) = hostile_near(monster_id, monster_pos, 1.5, objects) {
+        melee(monster_id, enemy_id, objects, game, fov_map);
+        return Ai::Pack;
+    }
+
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let sees_player = fov_map.is_in_fov(monster_x, monster_y);
+    match objects[monster_id].last_seen_player {
+        Some((player_x, player_y))
+            if !sees_player && (monster_x, monster_y) == (player_x, player_y) =>
+        {
+            // The trail has gone cold
+            objects[monster_id].last_seen_player = None;
+        }
+        Some((player_x, player_y)) => {
+            let (goal_x, goal_y) = match pack.tactics {
+                PackTactics::Surround if sees_player => {
+                    let close =
+                        objects[monster_id].distance_to(&objects[PLAYER]) <= PACK_AMBUSH_DISTANCE;
+                    if close && is_corridor(player_x, player_y, &game.map) {
+                        // Wait for the prey to come out into the open
+                        return Ai::Pack;
+                    }
+                    surround_spot(monster_id, pack.pack_id, game, objects)
+                        .unwrap_or((player_x, player_y))
+                }
+                _ => (player_x, player_y),
+            };
+            move_astar(monster_id, goal_x, goal_y, &game.map, objects);
+        }
+        None => {
+            // With the player nowhere to be seen the pack goes after whatever
+            // other enemies are around, followers stay close to their leader,
+            // and the leader goes about its business
+            let enemy_id =
+                nearest_visible_hostile(monster_id, TORCH_RADIUS as f32, game, objects, fov_map);
+            if let Some(enemy_id) = enemy_id {
+                engage(monster_id, enemy_id, game, objects, fov_map);
+            } else if let Some(leader_id) = leader_id.filter(|&id| id != monster_id) {
+                if objects[monster_id].distance_to(&objects[leader_id]) > 2.0 {
+                    let (leader_x, leader_y) = objects[leader_id].pos();
+                    move_astar(monster_id, leader_x, leader_y, &game.map, objects);
+                }
+            } else {
+                idle(monster_id, game, objects);
+            }
+        }
+    }
+    Ai::Pack
+}
+
+/// Pick a free tile next to the player that this monster is closer to than
+/// any of its packmates, so the pack fans out around the player instead of
+/// queueing up behind each other.
+fn surround_spot(
+    monster_id: usize,
+    pack_id: usize,
+    game: &Game,
+    objects: &[Object],
+) -> Option<(i32, i32)> {
+    let packmates: Vec<_> = objects
+        .iter()
+        .enumerate()
+        .filter(|&(id, object)| {
+            id != monster_id
+                && object.alive
+                && object.pack.is_some_and(|pack| pack.pack_id == pack_id)
+        })
+        .map(|(_, object)| object)
+        .collect();
+
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let mut best = None;
+    let mut best_score = f32::MAX;
+    for dx in -1..2 {
+        for dy in -1..2 {
+            let (x, y) = (player_x + dx, player_y + dy);
+            if (dx, dy) == (0, 0) || is_blocked(x, y, &game.map, objects) {
+                continue;
+            }
+            let closest_packmate = packmates
+                .iter()
+                .map(|packmate| packmate.distance(x, y))
+                .fold(f32::MAX, f32::min);
+            let score = objects[monster_id].distance(x, y) - closest_packmate;
+            if score < best_score {
+                best = Some((x, y));
+                best_score = score;
+            }
+        }
+    }
+    best
+}
+
+/// Whether the tile is in a narrow passage, with at most two open neighbours.
+fn is_corridor(x: i32, y: i32, map: &Map) -> bool {
+    let mut open_neighbours = 0;
+    for dx in -1..2 {
+        for dy in -1..2 {
+            if (dx, dy) != (0, 0) && !map[(x + dx) as usize][(y + dy) as usize].blocked {
+                open_neighbours += 1;
+            }
+        }
+    }
+    open_neighbours <= 2
+}
+
+/// Monsters remember where they last saw the player, and packs share what
+/// any one of their members can see.
+fn update_monster_knowledge(objects: &mut [Object], fov_map: &FovMap) {
+    let player_pos = objects[PLAYER].pos();
+    let mut alerted_packs = vec![];
+    for object in objects.iter_mut() {
+        if object.ai.is_some() && fov_map.is_in_fov(object.x, object.y) {
+            object.last_seen_player = Some(player_pos);
+            if let Some(pack) = object.pack {
+                alerted_packs.push(pack.pack_id);
+            }
+        }
+    }
+    for object in objects.iter_mut() {
+        let alerted = object
+            .pack
+            .is_some_and(|pack| alerted_packs.contains(&pack.pack_id));
+        if alerted {
+            object.last_seen_player = Some(player_pos);
+        }
+    }
+}
+
+fn ai_companion(
+    companion_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+    fov_map: &FovMap,
+    order: Order,
+) -> Ai {
+    let order = match order {
+        Order::Attack { target } => match hostile_near(companion_id, target, 1.5, objects) {
+            Some(target_id) => {
+                engage(companion_id, target_id, game, objects, fov_map);
+                let target = objects[target_id].pos();
+                return Ai::Companion {
+                    order: Order::Attack { target },
+                };
+            }
+            None => {
+                game.log.add(
+                    format!("Your {} returns to your side.", objects[companion_id].name),
+                    colors::LIGHT_GREEN,
+                );
+                Order::Follow
+            }
+        },
+        order => order,
+    };
+
+    let enemy_id =
+        nearest_visible_hostile(companion_id, COMPANION_ENGAGE_RANGE, game, objects, fov_map);
+    match (order, enemy_id) {
+        (Order::Stay, Some(enemy_id))
+            if objects[companion_id].distance_to(&objects[enemy_id]) < 2.0 =>
+        {
+            melee(companion_id, enemy_id, objects, game, fov_map);
+        }
+        (Order::Follow, Some(enemy_id)) => engage(companion_id, enemy_id, game, objects, fov_map),
+        (Order::Follow, None) if objects[companion_id].distance_to(&objects[PLAYER]) > 2.0 => {
             let (player_x, player_y) = objects[PLAYER].pos();
-            move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
-            let (monster, player) = mut_two(monster_id, PLAYER, objects);
-            monster.attack(player, game);
+            move_astar(companion_id, player_x, player_y, &game.map, objects);
         }
+        _ => {}
     }
-    Ai::Basic
+    Ai::Companion { order }
+}
+
+/// Attack the target if it's in reach, otherwise close in on it.
+fn engage(
+    attacker_id: usize,
+    target_id: usize,
+    game: &mut Game,
+    objects: &mut [Object],
+    fov_map: &FovMap,
+) {
+    if objects[attacker_id].distance_to(&objects[target_id]) < 2.0 {
+        melee(attacker_id, target_id, objects, game, fov_map);
+    } else {
+        let (target_x, target_y) = objects[target_id].pos();
+        move_astar(attacker_id, target_x, target_y, &game.map, objects);
+    }
+}
+
+/// The creature `viewer_id` is hostile to that is nearest to `pos`, if any
+/// is within `max_distance`.
+fn hostile_near(
+    viewer_id: usize,
+    pos: (i32, i32),
+    max_distance: f32,
+    objects: &[Object],
+) -> Option<usize> {
+    let mut closest = None;
+    let mut closest_distance = max_distance;
+    for (id, object) in objects.iter().enumerate() {
+        let hostile =
+            object.alive && object.fighter.is_some() && objects[viewer_id].is_hostile_to(object);
+        let distance = object.distance(pos.0, pos.1);
+        if hostile && distance <= closest_distance {
+            closest = Some(id);
+            closest_distance = distance;
+        }
+    }
+    closest
 }
 
-fn ai_confused(
+/// The closest creature within `max_distance` that `viewer_id` is hostile to
+/// and can see.
+fn nearest_visible_hostile(
+    viewer_id: usize,
+    max_distance: f32,
+    game: &Game,
+    objects: &[Object],
+    fov_map: &FovMap,
+) -> Option<usize> {
+    let mut closest = None;
+    let mut closest_distance = max_distance;
+    for (id, object) in objects.iter().enumerate() {
+        let hostile =
+            object.alive && object.fighter.is_some() && objects[viewer_id].is_hostile_to(object);
+        let distance = objects[viewer_id].distance_to(object);
+        if hostile
+            && distance <= closest_distance
+            && can_perceive(viewer_id, id, game, objects, fov_map)
+        {
+            closest = Some(id);
+            closest_distance = distance;
+        }
+    }
+    closest
+}
+
+/// Whether the viewer can see the target. The player's field of view works
+/// both ways, so it decides who sees the player; anything else needs a clear
+/// line within torch range. The blind only notice what's right next to them.
+fn can_perceive(
+    viewer_id: usize,
+    target_id: usize,
+    game: &Game,
+    objects: &[Object],
+    fov_map: &FovMap,
+) -> bool {
+    let viewer_pos = objects[viewer_id].pos();
+    let player_blind = objects[PLAYER].has_effect(Effect::Blindness);
+    if objects[viewer_id].has_effect(Effect::Blindness) {
+        objects[viewer_id].distance_to(&objects[target_id]) < 2.0
+    } else if target_id == PLAYER && !player_blind {
+        fov_map.is_in_fov(viewer_pos.0, viewer_pos.1)
+    } else {
+        objects[viewer_id].distance_to(&objects[target_id]) <= TORCH_RADIUS as f32
+            && can_see(viewer_pos, objects[target_id].pos(), &game.map)
+    }
+}
+
+/// Move a confused monster in a random direction. Stumbling into anyone,
+/// friend or foe, turns into an attack.
+fn stumble(monster_id: usize, game: &mut Game, objects: &mut [Object], fov_map: &FovMap) {
+    let dx = rand::thread_rng().gen_range(-1, 2);
+    let dy = rand::thread_rng().gen_range(-1, 2);
+    let (x, y) = (objects[monster_id].x + dx, objects[monster_id].y + dy);
+    let victim_id = objects
+        .iter()
+        .position(|object| object.pos() == (x, y) && object.alive && object.fighter.is_some());
+    match victim_id {
+        Some(victim_id) if victim_id != monster_id => {
+            melee(monster_id, victim_id, objects, game, fov_map);
+        }
+        _ => move_by(monster_id, dx, dy, &game.map, objects),
+    }
+}
+
+fn ai_fleeing(
     monster_id: usize,
     game: &mut Game,
     objects: &mut [Object],
+    fov_map: &FovMap,
     previous_ai: Box<Ai>,
-    num_turns: i32,
 ) -> Ai {
-    if num_turns >= 0 {
-        move_by(
-            monster_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            &game.map,
-            objects,
-        );
-        Ai::Confused {
-            previous_ai: previous_ai,
-            num_turns: num_turns - 1,
+    let threat_id =
+        nearest_visible_hostile(monster_id, TORCH_RADIUS as f32, game, objects, fov_map);
+    let threat_id = match threat_id {
+        Some(threat_id) => threat_id,
+        None => {
+            // With no enemy in sight the monster can collect itself
+            let recovered = objects[monster_id].morale.as_mut().is_none_or(|morale| {
+                morale.courage = cmp::min(morale.courage + MORALE_RECOVERY, morale.max_courage);
+                morale.has_recovered()
+            });
+            if recovered {
+                let monster = &objects[monster_id];
+                if fov_map.is_in_fov(monster.x, monster.y) {
+                    game.log.add(
+                        format!("The {} regains its courage!", monster.name),
+                        colors::LIGHT_ORANGE,
+                    );
+                }
+                return *previous_ai;
+            }
+            return Ai::Fleeing { previous_ai };
         }
+    };
+
+    if !flee_step(monster_id, threat_id, game, objects) {
+        // Cornered: lash out at the threat if it's close enough
+        if objects[monster_id].distance_to(&objects[threat_id]) < 2.0 {
+            melee(monster_id, threat_id, objects, game, fov_map);
+        }
+    }
+    Ai::Fleeing { previous_ai }
+}
+
+/// Move one step away from the threat, towards open ground rather than the
+/// nearest dead end. Returns false when there is nowhere better to go.
+fn flee_step(monster_id: usize, threat_id: usize, game: &Game, objects: &mut [Object]) -> bool {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let safety = DijkstraMap::to_object(threat_id, &game.map, objects)
+        .safety(|x, y| pathfinding::movement_cost(x, y, &game.map, objects));
+    let escape = safety.downhill(monster_x, monster_y, |x, y| {
+        !is_blocked(x, y, &game.map, objects)
+    });
+    match escape {
+        Some((x, y)) => {
+            objects[monster_id].set_pos(x, y);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Let everything but the player take a turn.
+fn world_turn(game: &mut Game, objects: &mut Vec<Object>, fov_map: &FovMap) {
+    spread_fear(game, objects);
+    update_monster_knowledge(objects, fov_map);
+    for id in 0..objects.len() {
+        for _ in 0..actions_per_turn(&objects[id], game.turn) {
+            if objects[id].ai.is_some() {
+                ai_take_turn(id, game, objects, fov_map);
+            }
+        }
+    }
+    status::tick(objects, game, fov_map);
+    drop_loot(objects, game);
+    rot_corpses(objects, game);
+    game.turn += 1;
+}
+
+/// Hasted creatures act twice a turn, and slowed ones every other turn.
+fn actions_per_turn(object: &Object, turn: u32) -> u32 {
+    if object.has_effect(Effect::Haste) {
+        2
+    } else if object.has_effect(Effect::Slow) {
+        turn % 2
     } else {
-        game.log.add(
-            format!("The {} is no longer confused!", objects[monster_id].name),
-            colors::RED,
-        );
-        *previous_ai
+        1
+    }
+}
+
+/// How many turns the world takes after the player acts: a hasted player
+/// gets two moves for each of the world's turns, a slowed one only half.
+fn world_turns_after_player_action(game: &mut Game, player: &Object) -> u32 {
+    if player.has_effect(Effect::Haste) {
+        game.player_bonus_action = !game.player_bonus_action;
+        if game.player_bonus_action {
+            0
+        } else {
+            1
+        }
+    } else if player.has_effect(Effect::Slow) {
+        2
+    } else {
+        1
     }
 }
 
-fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
+/// Returns the new map along with the centres of its rooms.
+fn make_map(
+    objects: &mut Vec<Object>,
+    level: u32,
+    uniques: &mut Vec<String>,
+) -> (Map, Vec<(i32, i32)>) {
     let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
     // Player is the first element. Remove everything else.
     assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
     objects.truncate(1);
 
     let mut rooms: Vec<Rect> = vec![];
+    let mut room_centers = vec![];
 
     for _ in 0..MAX_ROOMS {
         let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
@@ -194,8 +1042,9 @@ fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
 
         if !failed {
             create_room(new_room, &mut map);
-            place_objects(new_room, &map, objects, level);
+            place_objects(new_room, &map, objects, level, uniques);
             let (new_x, new_y) = new_room.center();
+            room_centers.push((new_x, new_y));
             if rooms.is_empty() {
                 objects[PLAYER].set_pos(new_x, new_y);
             } else {
@@ -233,12 +1082,45 @@ fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
     stairs.always_visible = true;
     objects.push(stairs);
 
-    map
+    assign_idle_behaviour(&room_centers, (last_room_x, last_room_y), objects);
+
+    (map, room_centers)
 }
 
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
-    use rand::distributions::{IndependentSample, Weighted, WeightedChoice};
+/// Give the monsters on a fresh level something to do until they notice the
+/// player. Those near the stairs guard them; the rest patrol a few rooms,
+/// wander around or simply wait. Pack followers stick with their leader.
+fn assign_idle_behaviour(rooms: &[(i32, i32)], stairs: (i32, i32), objects: &mut [Object]) {
+    for object in objects.iter_mut() {
+        let follower = object.pack.is_some_and(|pack| !pack.leader);
+        if object.ai.is_none() || object.companion || follower {
+            continue;
+        }
+        object.idle = if object.distance(stairs.0, stairs.1) <= GUARD_STAIRS_DISTANCE {
+            Some(Idle::Guard { post: object.pos() })
+        } else {
+            match rand::thread_rng().gen_range(0, 3) {
+                0 => {
+                    let mut route = vec![object.pos()];
+                    for _ in 0..rand::thread_rng().gen_range(1, 3) {
+                        route.extend(rand::thread_rng().choose(rooms));
+                    }
+                    Some(Idle::Patrol { route, next: 0 })
+                }
+                1 => Some(Idle::Wander { goal: None }),
+                _ => None,
+            }
+        };
+    }
+}
 
+fn place_objects(
+    room: Rect,
+    map: &Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    uniques: &mut Vec<String>,
+) {
     let max_monsters = from_dungeon_level(
         &[
             Transition { level: 1, value: 2 },
@@ -249,34 +1131,7 @@ fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
     );
     let num_monsters = rand::thread_rng().gen_range(0, max_monsters + 1);
 
-    let troll_chance = from_dungeon_level(
-        &[
-            Transition {
-                level: 3,
-                value: 15,
-            },
-            Transition {
-                level: 5,
-                value: 30,
-            },
-            Transition {
-                level: 7,
-                value: 60,
-            },
-        ],
-        level,
-    );
-
-    let monster_chances = &mut [
-        Weighted {
-            weight: 80,
-            item: "orc",
-        },
-        Weighted {
-            weight: troll_chance,
-            item: "troll",
-        },
-    ];
+    let monster_chances = &mut monster_chances(level);
     let monster_choice = WeightedChoice::new(monster_chances);
 
     for _ in 0..num_monsters {
@@ -284,37 +1139,17 @@ fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
         let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
 
         if !is_blocked(x, y, map, objects) {
-            let mut monster = match monster_choice.ind_sample(&mut rand::thread_rng()) {
-                "orc" => {
-                    let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
-                    orc.fighter = Some(Fighter {
-                        base_max_hp: 20,
-                        hp: 20,
-                        base_defense: 0,
-                        base_power: 4,
-                        xp: 35,
-                        on_death: DeathCallback::Monster,
-                    });
-                    orc.ai = Some(Ai::Basic);
-                    orc
+            let kind = monster_choice.ind_sample(&mut rand::thread_rng());
+            if let Some((tactics, members)) = pack_members(kind) {
+                place_pack(room, map, objects, tactics, &members);
+            } else if is_unique(kind) {
+                if !uniques.iter().any(|unique| unique == kind) {
+                    uniques.push(kind.to_string());
+                    objects.extend(new_monster(kind, x, y));
                 }
-                "troll" => {
-                    let mut troll = Object::new(x, y, 'T', "troll", colors::DARKER_GREEN, true);
-                    troll.fighter = Some(Fighter {
-                        base_max_hp: 30,
-                        hp: 30,
-                        base_defense: 2,
-                        base_power: 8,
-                        xp: 100,
-                        on_death: DeathCallback::Monster,
-                    });
-                    troll.ai = Some(Ai::Basic);
-                    troll
-                }
-                _ => unreachable!(),
-            };
-            monster.alive = true;
-            objects.push(monster);
+            } else {
+                objects.extend(new_monster(kind, x, y));
+            }
         }
     }
 
@@ -329,30 +1164,98 @@ fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
     );
     let num_items = rand::thread_rng().gen_range(0, max_items + 1);
 
-    let item_chances = &mut [
+    let catalogue = catalogue::load();
+    let item_chances = &mut catalogue
+        .iter()
+        .map(|def| Weighted {
+            weight: def.spawn_weight(level),
+            item: def,
+        })
+        .collect::<Vec<_>>();
+    let item_choice = WeightedChoice::new(item_chances);
+
+    for _ in 0..num_items {
+        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
+        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+
+        if !is_blocked(x, y, map, objects) {
+            let mut rng = rand::thread_rng();
+            let mut item = item_choice.ind_sample(&mut rng).create(x, y, &mut rng);
+            item.always_visible = true;
+            objects.push(item);
+        }
+    }
+}
+
+/// Which monsters turn up on this level, and how likely each one is. Packs
+/// are listed under the names `pack_members` knows them by.
+fn monster_chances(level: u32) -> Vec<Weighted<&'static str>> {
+    let troll_chance = from_dungeon_level(
+        &[
+            Transition {
+                level: 3,
+                value: 15,
+            },
+            Transition {
+                level: 5,
+                value: 30,
+            },
+            Transition {
+                level: 7,
+                value: 60,
+            },
+        ],
+        level,
+    );
+
+    vec![
+        Weighted {
+            weight: 80,
+            item: "orc",
+        },
         Weighted {
-            weight: 35,
-            item: Item::Heal,
+            weight: 30,
+            item: "goblin",
+        },
+        Weighted {
+            weight: from_dungeon_level(
+                &[Transition {
+                    level: 2,
+                    value: 20,
+                }],
+                level,
+            ),
+            item: "orc archer",
         },
         Weighted {
             weight: from_dungeon_level(
                 &[Transition {
                     level: 4,
-                    value: 25,
+                    value: 15,
+                }],
+                level,
+            ),
+            item: "cultist",
+        },
+        Weighted {
+            weight: from_dungeon_level(
+                &[Transition {
+                    level: 3,
+                    value: 10,
                 }],
                 level,
             ),
-            item: Item::Lightning,
+            item: "orc shaman",
         },
         Weighted {
             weight: from_dungeon_level(
                 &[Transition {
                     level: 6,
-                    value: 25,
+                    value: 10,
                 }],
                 level,
             ),
-            item: Item::Fireball,
+            item: "necromancer",
         },
         Weighted {
             weight: from_dungeon_level(
@@ -362,94 +1265,697 @@ fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
                 }],
                 level,
             ),
-            item: Item::Confuse,
+            item: "wolf pack",
+        },
+        Weighted {
+            weight: from_dungeon_level(
+                &[Transition {
+                    level: 4,
+                    value: 10,
+                }],
+                level,
+            ),
+            item: "orc war band",
         },
         Weighted {
-            weight: from_dungeon_level(&[Transition { level: 4, value: 5 }], level),
-            item: Item::Sword,
+            weight: troll_chance,
+            item: "troll",
         },
         Weighted {
             weight: from_dungeon_level(
                 &[Transition {
-                    level: 8,
-                    value: 15,
+                    level: 5,
+                    value: 10,
                 }],
                 level,
             ),
-            item: Item::Shield,
+            item: "fire troll",
         },
-    ];
-    let item_choice = WeightedChoice::new(item_chances);
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 3, value: 3 }], level),
+            item: "Ugluk",
+        },
+        Weighted {
+            weight: from_dungeon_level(&[Transition { level: 6, value: 3 }], level),
+            item: "Vesna",
+        },
+    ]
+}
 
-    for _ in 0..num_items {
-        let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+/// The kinds of monster that make up a pack, the war band's leader first, or
+/// `None` when the kind turns up on its own.
+fn pack_members(kind: &str) -> Option<(PackTactics, Vec<&'static str>)> {
+    match kind {
+        "wolf pack" => {
+            let pack_size = rand::thread_rng().gen_range(3, 5);
+            Some((PackTactics::Surround, vec!["wolf"; pack_size]))
+        }
+        "orc war band" => {
+            let band_size = rand::thread_rng().gen_range(2, 4);
+            let mut members = vec!["orc warlord"];
+            members.extend(vec!["orc"; band_size]);
+            Some((PackTactics::WarBand, members))
+        }
+        _ => None,
+    }
+}
 
-        if !is_blocked(x, y, map, objects) {
-            let mut item = match item_choice.ind_sample(&mut rand::thread_rng()) {
-                Item::Heal => {
-                    let mut object =
-                        Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::Lightning => {
-                    let mut object = Object::new(
-                        x,
-                        y,
-                        '#',
-                        "scroll of lightning bolt",
-                        colors::LIGHT_YELLOW,
-                        false,
-                    );
-                    object.item = Some(Item::Lightning);
-                    object
-                }
-                Item::Fireball => {
-                    let mut object =
-                        Object::new(x, y, '#', "scroll of fireball", colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Fireball);
-                    object
-                }
-                Item::Confuse => {
-                    let mut object = Object::new(
-                        x,
-                        y,
-                        '#',
-                        "scroll of confusion",
-                        colors::LIGHT_YELLOW,
-                        false,
-                    );
-                    object.item = Some(Item::Confuse);
-                    object
-                }
-                Item::Sword => {
-                    let mut object = Object::new(x, y, '/', "sword", colors::SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::RightHand,
-                        max_hp_bonus: 0,
-                        power_bonus: 3,
-                        defense_bonus: 0,
-                    });
-                    object
+fn is_unique(kind: &str) -> bool {
+    kind == "Ugluk" || kind == "Vesna"
+}
+
+fn new_gold(amount: i32, x: i32, y: i32) -> Object {
+    let mut gold = Object::new(x, y, '$', "gold", colors::GOLD, false);
+    gold.item = Some(Item::Gold);
+    gold.gold = Some(amount);
+    gold
+}
+
+/// Something a monster can leave behind when it dies.
+#[derive(Clone, Copy, Debug)]
+enum Drop {
+    Nothing,
+    Gold,
+    /// An item from the catalogue, by name
+    Item(&'static str),
+}
+
+/// What each loot table holds, and how likely each drop is on this level.
+fn loot_chances(table: LootTable, level: u32) -> Vec<Weighted<Drop>> {
+    match table {
+        LootTable::Orcs => vec![
+            Weighted {
+                weight: 50,
+                item: Drop::Nothing,
+            },
+            Weighted {
+                weight: 30,
+                item: Drop::Gold,
+            },
+            Weighted {
+                weight: 10,
+                item: Drop::Item("healing potion"),
+            },
+            Weighted {
+                weight: from_dungeon_level(
+                    &[Transition {
+                        level: 2,
+                        value: 10,
+                    }],
+                    level,
+                ),
+                item: Drop::Item("arrow"),
+            },
+            Weighted {
+                weight: from_dungeon_level(&[Transition { level: 3, value: 5 }], level),
+                item: Drop::Item("sword"),
+            },
+        ],
+        LootTable::Goblins => vec![
+            Weighted {
+                weight: 50,
+                item: Drop::Nothing,
+            },
+            Weighted {
+                weight: 20,
+                item: Drop::Gold,
+            },
+            Weighted {
+                weight: 15,
+                item: Drop::Item("sling stone"),
+            },
+            Weighted {
+                weight: 5,
+                item: Drop::Item("sling"),
+            },
+            Weighted {
+                weight: from_dungeon_level(&[Transition { level: 2, value: 5 }], level),
+                item: Drop::Item("potion of blindness"),
+            },
+        ],
+        LootTable::Casters => vec![
+            Weighted {
+                weight: 40,
+                item: Drop::Nothing,
+            },
+            Weighted {
+                weight: 25,
+                item: Drop::Gold,
+            },
+            Weighted {
+                weight: 10,
+                item: Drop::Item("scroll of lightning bolt"),
+            },
+            Weighted {
+                weight: 10,
+                item: Drop::Item("scroll of confusion"),
+            },
+            Weighted {
+                weight: 5,
+                item: Drop::Item("potion of telepathy"),
+            },
+            Weighted {
+                weight: from_dungeon_level(
+                    &[Transition {
+                        level: 5,
+                        value: 10,
+                    }],
+                    level,
+                ),
+                item: Drop::Item("scroll of fireball"),
+            },
+        ],
+        LootTable::Trolls => vec![
+            Weighted {
+                weight: 30,
+                item: Drop::Nothing,
+            },
+            Weighted {
+                weight: 40,
+                item: Drop::Gold,
+            },
+            Weighted {
+                weight: 15,
+                item: Drop::Item("healing potion"),
+            },
+            Weighted {
+                weight: 10,
+                item: Drop::Item("potion of giant strength"),
+            },
+            Weighted {
+                weight: from_dungeon_level(
+                    &[Transition {
+                        level: 6,
+                        value: 10,
+                    }],
+                    level,
+                ),
+                item: Drop::Item("shield"),
+            },
+        ],
+    }
+}
+
+/// Leave the loot of creatures that have just died on top of their corpses.
+/// Gold is worth more the deeper the player is.
+fn drop_loot(objects: &mut Vec<Object>, game: &mut Game) {
+    let level = game.dungeon_level;
+    for id in 0..objects.len() {
+        if objects[id].alive {
+            continue;
+        }
+        let loot = match objects[id].loot.take() {
+            Some(loot) => loot,
+            None => continue,
+        };
+        let (x, y) = objects[id].pos();
+        let mut drops: Vec<_> = loot
+            .guaranteed
+            .iter()
+            .map(|name| catalogue::new_item(name, x, y, &mut game.rng))
+            .collect();
+        let mut chances = loot_chances(loot.table, level);
+        let choice = WeightedChoice::new(&mut chances);
+        for _ in 0..loot.rolls {
+            match choice.ind_sample(&mut game.rng) {
+                Drop::Nothing => {}
+                Drop::Gold => {
+                    let amount = game.rng.gen_range(2, 11) * level as i32;
+                    drops.push(new_gold(amount, x, y));
                 }
-                Item::Shield => {
-                    let mut object = Object::new(x, y, '[', "shield", colors::DARKER_ORANGE, false);
-                    object.item = Some(Item::Shield);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::LeftHand,
-                        max_hp_bonus: 0,
-                        defense_bonus: 1,
-                        power_bonus: 0,
+                Drop::Item(name) => drops.push(catalogue::new_item(name, x, y, &mut game.rng)),
+            }
+        }
+        for mut drop in drops {
+            game.identification.disguise(&mut drop);
+            drop.always_visible = true;
+            objects.push(drop);
+        }
+    }
+}
+
+/// Corpses rot away after a while, whether on the floor or in the pack.
+fn rot_corpses(objects: &mut Vec<Object>, game: &mut Game) {
+    for object in objects.iter_mut().chain(game.inventory.iter_mut()) {
+        if let Some(turns) = object.decay.as_mut() {
+            *turns -= 1;
+        }
+    }
+    let rotten = |object: &Object| object.decay.is_some_and(|turns| turns <= 0);
+    let rotten_in_pack: Vec<_> = game
+        .inventory
+        .iter()
+        .filter(|item| rotten(item))
+        .map(|item| item.name.clone())
+        .collect();
+    for name in rotten_in_pack {
+        game.log.add(
+            format!("The {} in your pack rots away.", name),
+            colors::DARK_RED,
+        );
+    }
+    game.inventory.retain(|item| !rotten(item));
+    objects.retain(|object| !rotten(object));
+}
+
+/// A monster of the given kind, or `None` if there's no such kind.
+fn new_monster(kind: &str, x: i32, y: i32) -> Option<Object> {
+    let mut monster = match kind {
+        "orc" => {
+            let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
+            orc.fighter = Some(Fighter {
+                base_max_hp: 20,
+                hp: 20,
+                base_defense: 0,
+                base_power: 4,
+                base_accuracy: 0,
+                base_evasion: 0,
+                damage: Dice { count: 1, sides: 3 },
+                damage_type: DamageType::Physical,
+                xp: 35,
+                on_death: DeathCallback::Monster,
+            });
+            orc.faction = Some(Faction::Orcs);
+            orc.loot = Some(Loot {
+                table: LootTable::Orcs,
+                rolls: 1,
+                guaranteed: vec![],
+            });
+            orc.ai = Some(Ai::Basic);
+            orc.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 50,
+            });
+            orc
+        }
+        "goblin" => {
+            let mut goblin = Object::new(x, y, 'g', "goblin", colors::LIGHT_CHARTREUSE, true);
+            goblin.fighter = Some(Fighter {
+                base_max_hp: 12,
+                hp: 12,
+                base_defense: 0,
+                base_power: 3,
+                base_accuracy: 0,
+                base_evasion: 3,
+                damage: Dice { count: 1, sides: 3 },
+                damage_type: DamageType::Poison,
+                xp: 25,
+                on_death: DeathCallback::Monster,
+            });
+            goblin.faction = Some(Faction::Goblins);
+            goblin.loot = Some(Loot {
+                table: LootTable::Goblins,
+                rolls: 1,
+                guaranteed: vec![],
+            });
+            goblin.ai = Some(Ai::Basic);
+            goblin.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 60,
+            });
+            goblin
+        }
+        "orc archer" => {
+            let mut archer = Object::new(x, y, 'o', "orc archer", colors::DARKER_LIME, true);
+            archer.fighter = Some(Fighter {
+                base_max_hp: 15,
+                hp: 15,
+                base_defense: 0,
+                base_power: 3,
+                base_accuracy: 1,
+                base_evasion: 1,
+                damage: Dice { count: 1, sides: 3 },
+                damage_type: DamageType::Physical,
+                xp: 40,
+                on_death: DeathCallback::Monster,
+            });
+            archer.faction = Some(Faction::Orcs);
+            archer.loot = Some(Loot {
+                table: LootTable::Orcs,
+                rolls: 1,
+                guaranteed: vec![],
+            });
+            archer.ai = Some(Ai::Ranged);
+            archer.ranged = Some(RangedAttack {
+                missile: Missile::Arrow,
+                range: 6,
+                preferred_distance: 4,
+            });
+            archer.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 50,
+            });
+            archer
+        }
+        "cultist" => {
+            let mut cultist = Object::new(x, y, 'c', "cultist", colors::DARK_VIOLET, true);
+            cultist.fighter = Some(Fighter {
+                base_max_hp: 18,
+                hp: 18,
+                base_defense: 0,
+                base_power: 5,
+                base_accuracy: 0,
+                base_evasion: 1,
+                damage: Dice { count: 1, sides: 4 },
+                damage_type: DamageType::Physical,
+                xp: 60,
+                on_death: DeathCallback::Monster,
+            });
+            cultist.faction = Some(Faction::Cultists);
+            cultist.loot = Some(Loot {
+                table: LootTable::Casters,
+                rolls: 1,
+                guaranteed: vec![],
+            });
+            cultist.ai = Some(Ai::Behaviour {
+                name: "cultist".into(),
+            });
+            cultist.spells = vec![KnownSpell::new(Spell::Fireball, 20)];
+            cultist.ranged = Some(RangedAttack {
+                missile: Missile::FrostBolt,
+                range: 7,
+                preferred_distance: 5,
+            });
+            cultist.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 30,
+            });
+            cultist
+        }
+        "orc shaman" => {
+            let mut shaman = Object::new(x, y, 'o', "orc shaman", colors::LIGHT_VIOLET, true);
+            shaman.fighter = Some(Fighter {
+                base_max_hp: 16,
+                hp: 16,
+                base_defense: 0,
+                base_power: 3,
+                base_accuracy: 0,
+                base_evasion: 0,
+                damage: Dice { count: 1, sides: 3 },
+                damage_type: DamageType::Physical,
+                xp: 60,
+                on_death: DeathCallback::Monster,
+            });
+            shaman.faction = Some(Faction::Orcs);
+            shaman.loot = Some(Loot {
+                table: LootTable::Casters,
+                rolls: 1,
+                guaranteed: vec![],
+            });
+            shaman.ai = Some(Ai::Behaviour {
+                name: "orc shaman".into(),
+            });
+            shaman.spells = vec![
+                KnownSpell::new(Spell::Heal, 5),
+                KnownSpell::new(Spell::Confuse, 15),
+            ];
+            shaman.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 60,
+            });
+            shaman
+        }
+        "necromancer" => {
+            let mut necromancer =
+                Object::new(x, y, 'N', "necromancer", colors::DARKER_VIOLET, true);
+            necromancer.fighter = Some(Fighter {
+                base_max_hp: 25,
+                hp: 25,
+                base_defense: 1,
+                base_power: 4,
+                base_accuracy: 0,
+                base_evasion: 1,
+                damage: Dice { count: 1, sides: 4 },
+                damage_type: DamageType::Physical,
+                xp: 150,
+                on_death: DeathCallback::Monster,
+            });
+            necromancer.faction = Some(Faction::Cultists);
+            necromancer.loot = Some(Loot {
+                table: LootTable::Casters,
+                rolls: 2,
+                guaranteed: vec![],
+            });
+            necromancer.resistances.cold = 50;
+            necromancer.ai = Some(Ai::Behaviour {
+                name: "necromancer".into(),
+            });
+            necromancer.spells = vec![KnownSpell::new(Spell::Lightning, 20)];
+            necromancer.ranged = Some(RangedAttack {
+                missile: Missile::FrostBolt,
+                range: 6,
+                preferred_distance: 4,
+            });
+            necromancer
+        }
+        "skeleton" => {
+            let mut skeleton = Object::new(x, y, 's', "skeleton", colors::LIGHTEST_GREY, true);
+            skeleton.fighter = Some(Fighter {
+                base_max_hp: 10,
+                hp: 10,
+                base_defense: 0,
+                base_power: 3,
+                base_accuracy: 0,
+                base_evasion: 0,
+                damage: Dice { count: 1, sides: 3 },
+                damage_type: DamageType::Physical,
+                xp: 15,
+                on_death: DeathCallback::Monster,
+            });
+            // The dead know no fear, so skeletons have no morale
+            skeleton.faction = Some(Faction::Cultists);
+            skeleton.resistances.cold = 50;
+            skeleton.resistances.poison = 100;
+            skeleton.ai = Some(Ai::Basic);
+            skeleton
+        }
+        "troll" => {
+            let mut troll = Object::new(x, y, 'T', "troll", colors::DARKER_GREEN, true);
+            troll.fighter = Some(Fighter {
+                base_max_hp: 30,
+                hp: 30,
+                base_defense: 2,
+                base_power: 8,
+                base_accuracy: 0,
+                base_evasion: -2,
+                damage: Dice { count: 1, sides: 6 },
+                damage_type: DamageType::Physical,
+                xp: 100,
+                on_death: DeathCallback::Monster,
+            });
+            troll.faction = Some(Faction::Trolls);
+            troll.loot = Some(Loot {
+                table: LootTable::Trolls,
+                rolls: 1,
+                guaranteed: vec![],
+            });
+            // Trolls are famously afraid of fire, with good reason
+            troll.resistances.fire = -50;
+            troll.ai = Some(Ai::Basic);
+            troll.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 15,
+            });
+            troll
+        }
+        "fire troll" => {
+            let mut troll = Object::new(x, y, 'T', "fire troll", colors::FLAME, true);
+            troll.fighter = Some(Fighter {
+                base_max_hp: 35,
+                hp: 35,
+                base_defense: 2,
+                base_power: 7,
+                base_accuracy: 0,
+                base_evasion: -2,
+                damage: Dice { count: 1, sides: 6 },
+                damage_type: DamageType::Fire,
+                xp: 150,
+                on_death: DeathCallback::Monster,
+            });
+            troll.faction = Some(Faction::Trolls);
+            troll.loot = Some(Loot {
+                table: LootTable::Trolls,
+                rolls: 1,
+                guaranteed: vec![],
+            });
+            troll.resistances.fire = 90;
+            troll.resistances.cold = -50;
+            troll.ai = Some(Ai::Basic);
+            troll.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 15,
+            });
+            troll
+        }
+        "wolf" => {
+            let mut wolf = Object::new(x, y, 'w', "wolf", colors::LIGHT_SEPIA, true);
+            wolf.fighter = Some(Fighter {
+                base_max_hp: 10,
+                hp: 10,
+                base_defense: 0,
+                base_power: 3,
+                base_accuracy: 1,
+                base_evasion: 3,
+                damage: Dice { count: 1, sides: 4 },
+                damage_type: DamageType::Physical,
+                xp: 20,
+                on_death: DeathCallback::Monster,
+            });
+            wolf.faction = Some(Faction::Wolves);
+            wolf.ai = Some(Ai::Basic);
+            wolf.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 40,
+            });
+            wolf
+        }
+        "orc warlord" => {
+            let mut warlord = Object::new(x, y, 'O', "orc warlord", colors::DARK_GREEN, true);
+            warlord.fighter = Some(Fighter {
+                base_max_hp: 30,
+                hp: 30,
+                base_defense: 1,
+                base_power: 6,
+                base_accuracy: 2,
+                base_evasion: 1,
+                damage: Dice { count: 1, sides: 6 },
+                damage_type: DamageType::Physical,
+                xp: 120,
+                on_death: DeathCallback::Monster,
+            });
+            warlord.faction = Some(Faction::Orcs);
+            warlord.loot = Some(Loot {
+                table: LootTable::Orcs,
+                rolls: 2,
+                guaranteed: vec![],
+            });
+            warlord.ai = Some(Ai::Basic);
+            warlord.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 10,
+            });
+            warlord
+        }
+        // Uniques only ever appear once a game, and always carry something
+        // worth having.
+        "Ugluk" => {
+            let mut ugluk = Object::new(x, y, 'O', "Ugluk", colors::LIGHT_RED, true);
+            ugluk.fighter = Some(Fighter {
+                base_max_hp: 45,
+                hp: 45,
+                base_defense: 2,
+                base_power: 7,
+                base_accuracy: 3,
+                base_evasion: 1,
+                damage: Dice { count: 1, sides: 8 },
+                damage_type: DamageType::Physical,
+                xp: 300,
+                on_death: DeathCallback::Monster,
+            });
+            ugluk.faction = Some(Faction::Orcs);
+            ugluk.loot = Some(Loot {
+                table: LootTable::Orcs,
+                rolls: 3,
+                guaranteed: vec!["sword".into(), "shield".into()],
+            });
+            ugluk.ai = Some(Ai::Basic);
+            ugluk.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 0,
+            });
+            ugluk
+        }
+        "Vesna" => {
+            let mut vesna = Object::new(x, y, 'c', "Vesna", colors::LIGHT_MAGENTA, true);
+            vesna.fighter = Some(Fighter {
+                base_max_hp: 35,
+                hp: 35,
+                base_defense: 1,
+                base_power: 5,
+                base_accuracy: 2,
+                base_evasion: 3,
+                damage: Dice { count: 1, sides: 6 },
+                damage_type: DamageType::Physical,
+                xp: 400,
+                on_death: DeathCallback::Monster,
+            });
+            vesna.faction = Some(Faction::Cultists);
+            vesna.loot = Some(Loot {
+                table: LootTable::Casters,
+                rolls: 3,
+                guaranteed: vec![
+                    "scroll of charm monster".into(),
+                    "scroll of fireball".into(),
+                ],
+            });
+            vesna.resistances.fire = 50;
+            vesna.ai = Some(Ai::Behaviour {
+                name: "cultist".into(),
+            });
+            vesna.spells = vec![KnownSpell::new(Spell::Fireball, 12)];
+            vesna.ranged = Some(RangedAttack {
+                missile: Missile::FrostBolt,
+                range: 7,
+                preferred_distance: 5,
+            });
+            vesna.morale = Some(Morale {
+                courage: 100,
+                max_courage: 100,
+                flee_below: 20,
+            });
+            vesna
+        }
+        _ => return None,
+    };
+    monster.alive = true;
+    Some(monster)
+}
+
+/// Spawn a group of monsters in the room that share a pack id. For war bands
+/// the first member is the leader, and there's no war band without one.
+fn place_pack(
+    room: Rect,
+    map: &Map,
+    objects: &mut Vec<Object>,
+    tactics: PackTactics,
+    members: &[&str],
+) {
+    // Ids only need to be unique on this level, and the object list only
+    // grows while the level is being generated.
+    let pack_id = objects.len();
+    for (index, kind) in members.iter().enumerate() {
+        let leader = tactics == PackTactics::WarBand && index == 0;
+        let mut placed = false;
+        // Give up on a member if the room is too crowded to fit it
+        for _ in 0..10 {
+            let x = rand::thread_rng().gen_range(room.x1 + 1, room.x2);
+            let y = rand::thread_rng().gen_range(room.y1 + 1, room.y2);
+            if !is_blocked(x, y, map, objects) {
+                if let Some(mut monster) = new_monster(kind, x, y) {
+                    monster.ai = Some(Ai::Pack);
+                    monster.pack = Some(PackMember {
+                        pack_id,
+                        tactics,
+                        leader,
                     });
-                    object
+                    objects.push(monster);
+                    placed = true;
                 }
-            };
-            item.always_visible = true;
-            objects.push(item);
+                break;
+            }
+        }
+        if leader && !placed {
+            return;
         }
     }
 }
@@ -464,6 +1970,23 @@ fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mu
     move_by(id, dx, dy, map, objects);
 }
 
+/// Take one step along the shortest path to the target, going around walls
+/// and other creatures. Falls back to `move_towards` when there's no path.
+fn move_astar(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+    let path = pathfinding::find_path(objects[id].pos(), (target_x, target_y), |x, y| {
+        pathfinding::movement_cost(x, y, map, objects)
+    });
+    match path.and_then(|path| path.first().cloned()) {
+        Some((x, y)) => {
+            // The next step may be taken by another creature; wait for it to move.
+            if !is_blocked(x, y, map, objects) {
+                objects[id].set_pos(x, y);
+            }
+        }
+        None => move_towards(id, target_x, target_y, map, objects),
+    }
+}
+
 fn initialize_fov(map: &Map, tcod: &mut Tcod) {
     for y in 0..MAP_HEIGHT {
         for x in 0..MAP_WIDTH {
@@ -481,33 +2004,70 @@ fn initialize_fov(map: &Map, tcod: &mut Tcod) {
 fn new_game(tcod: &mut Tcod) -> (Vec<Object>, Game) {
     let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
     player.alive = true;
+    player.faction = Some(Faction::Player);
     player.fighter = Some(Fighter {
         base_max_hp: 100,
         hp: 100,
         base_defense: 1,
         base_power: 2,
+        base_accuracy: 2,
+        base_evasion: 2,
+        damage: Dice { count: 1, sides: 2 },
+        damage_type: DamageType::Physical,
         xp: 0,
         on_death: DeathCallback::Player,
     });
 
     let mut objects = vec![player];
 
+    let mut uniques = vec![];
+    let (map, rooms) = make_map(&mut objects, 1, &mut uniques);
+    let mut rng = GameRng::default();
+    let identification = Identification::new(&catalogue::load(), &mut rng);
     let mut game = Game {
-        map: make_map(&mut objects, 1),
+        map,
+        rooms,
         log: vec![],
         inventory: vec![],
         dungeon_level: 1,
+        turn: 0,
+        player_bonus_action: false,
+        recent_deaths: vec![],
+        gold: 0,
+        uniques,
+        attacks: vec![],
+        damage_stats: vec![],
+        identification,
+        exploring: None,
+        rng,
     };
-
-    let mut dagger = Object::new(0, 0, '-', "dagger", colors::SKY, false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::LeftHand,
-        max_hp_bonus: 0,
-        defense_bonus: 0,
-        power_bonus: 2,
+    game.identification.disguise_all(&mut objects);
+
+    let mut dog = Object::new(0, 0, 'd', "dog", colors::LIGHT_AMBER, true);
+    dog.alive = true;
+    dog.companion = true;
+    dog.faction = Some(Faction::Player);
+    dog.fighter = Some(Fighter {
+        base_max_hp: 25,
+        hp: 25,
+        base_defense: 0,
+        base_power: 3,
+        base_accuracy: 2,
+        base_evasion: 3,
+        damage: Dice { count: 1, sides: 4 },
+        damage_type: DamageType::Physical,
+        xp: 0,
+        on_death: DeathCallback::Companion,
+    });
+    dog.ai = Some(Ai::Companion {
+        order: Order::Follow,
     });
+    place_near_player(dog, &game.map, &mut objects);
+
+    let mut dagger = catalogue::new_item(STARTING_WEAPON, 0, 0, &mut game.rng);
+    if let Some(equipment) = dagger.equipment.as_mut() {
+        equipment.equipped = true;
+    }
     game.inventory.push(dagger);
 
     initialize_fov(&game.map, tcod);
@@ -522,6 +2082,7 @@ fn new_game(tcod: &mut Tcod) -> (Vec<Object>, Game) {
 
 fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) {
     let mut previous_player_position = (-1, -1);
+    let mut previously_blind = false;
 
     let mut key = Default::default();
 
@@ -532,7 +2093,8 @@ fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) {
             _ => key = Default::default(),
         }
 
-        let fov_recompute = previous_player_position != (objects[PLAYER].pos());
+        let fov_recompute = previous_player_position != (objects[PLAYER].pos())
+            || previously_blind != objects[PLAYER].has_effect(Effect::Blindness);
         render_all(tcod, &objects, game, fov_recompute);
 
         tcod.root.flush();
@@ -544,17 +2106,27 @@ fn play_game(objects: &mut Vec<Object>, game: &mut Game, tcod: &mut Tcod) {
         }
 
         previous_player_position = objects[PLAYER].pos();
-        let player_action = handle_keys(key, tcod, game, objects);
+        previously_blind = objects[PLAYER].has_effect(Effect::Blindness);
+        let player_action =
+            if objects[PLAYER].alive && objects[PLAYER].has_effect(Effect::Paralysis) {
+                // The world goes on without the player
+                PlayerAction::TookTurn
+            } else if game.exploring.is_some() && key.code == input::KeyCode::NoKey {
+                player_auto_explore(game, objects, tcod)
+            } else {
+                // Any key press stops the player exploring
+                game.exploring = None;
+                handle_keys(key, tcod, game, objects)
+            };
         if player_action == PlayerAction::Exit {
             save_game(objects, game).unwrap();
             break;
         }
 
         if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
-            for id in 0..objects.len() {
-                if objects[id].ai.is_some() {
-                    ai_take_turn(id, game, objects, &tcod.fov);
-                }
+            drop_loot(objects, game);
+            for _ in 0..world_turns_after_player_action(game, &objects[PLAYER]) {
+                world_turn(game, objects, &tcod.fov);
             }
         }
     }
@@ -581,7 +2153,26 @@ fn main() {
     main_menu(&mut tcod);
 }
 
-fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) {
+fn player_move_or_attack(
+    dx: i32,
+    dy: i32,
+    game: &mut Game,
+    objects: &mut [Object],
+    fov_map: &FovMap,
+) {
+    // A confused player stumbles in a random direction
+    let (dx, dy) = if objects[PLAYER].has_effect(Effect::Confusion) {
+        (
+            rand::thread_rng().gen_range(-1, 2),
+            rand::thread_rng().gen_range(-1, 2),
+        )
+    } else {
+        (dx, dy)
+    };
+    if (dx, dy) == (0, 0) {
+        return;
+    }
+
     let x = objects[PLAYER].x + dx;
     let y = objects[PLAYER].y + dy;
 
@@ -590,9 +2181,18 @@ fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Objec
         .position(|object| object.fighter.is_some() && object.pos() == (x, y));
 
     match target_id {
+        Some(companion_id) if objects[companion_id].companion => {
+            // Trade places instead of attacking a friend
+            let (player_x, player_y) = objects[PLAYER].pos();
+            objects[companion_id].set_pos(player_x, player_y);
+            objects[PLAYER].set_pos(x, y);
+            game.log.add(
+                format!("You swap places with your {}.", objects[companion_id].name),
+                colors::WHITE,
+            );
+        }
         Some(target_id) => {
-            let (player, target) = mut_two(PLAYER, target_id, objects);
-            player.attack(target, game);
+            melee(PLAYER, target_id, objects, game, fov_map);
         }
         None => {
             move_by(PLAYER, dx, dy, &game.map, objects);
@@ -600,6 +2200,98 @@ fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Objec
     }
 }
 
+fn hostile_in_view<'a>(objects: &'a [Object], tcod: &Tcod) -> Option<&'a Object> {
+    objects.iter().find(|object| {
+        object.ai.is_some()
+            && objects[PLAYER].is_hostile_to(object)
+            && tcod.fov.is_in_fov(object.x, object.y)
+    })
+}
+
+/// The items and stairs the player can see right now.
+fn sights<'a>(objects: &'a [Object], tcod: &Tcod) -> Vec<&'a Object> {
+    objects
+        .iter()
+        .filter(|object| object.item.is_some() || object.name == "stairs")
+        .filter(|object| tcod.fov.is_in_fov(object.x, object.y))
+        .collect()
+}
+
+/// Set off exploring. The player keeps on walking by themselves, one step
+/// a turn, until something comes up or a key is pressed.
+fn start_auto_explore(game: &mut Game, objects: &mut [Object], tcod: &Tcod) -> PlayerAction {
+    if hostile_in_view(objects, tcod).is_some() {
+        game.log
+            .add("You can't explore with enemies in view!", colors::RED);
+        return PlayerAction::DidntTakeTurn;
+    }
+    game.exploring = Some(Exploration {
+        hp: objects[PLAYER].fighter.map_or(0, |f| f.hp),
+        seen: sights(objects, tcod)
+            .iter()
+            .map(|object| object.pos())
+            .collect(),
+    });
+    player_auto_explore(game, objects, tcod)
+}
+
+/// Take the next step towards the nearest unexplored part of the level, or
+/// towards the stairs once everything reachable has been seen. Exploring
+/// stops when an enemy comes into view, the player gets hurt, something
+/// new turns up or there is nowhere left to go.
+fn player_auto_explore(game: &mut Game, objects: &mut [Object], tcod: &Tcod) -> PlayerAction {
+    let mut exploration = match game.exploring.take() {
+        Some(exploration) => exploration,
+        None => return PlayerAction::DidntTakeTurn,
+    };
+    if !objects[PLAYER].alive {
+        return PlayerAction::DidntTakeTurn;
+    }
+    let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+    let found = sights(objects, tcod)
+        .into_iter()
+        .find(|object| !exploration.seen.contains(&object.pos()));
+    let interruption = if let Some(monster) = hostile_in_view(objects, tcod) {
+        Some(format!(
+            "You spot {} and stop exploring.",
+            monster.indefinite_name()
+        ))
+    } else if hp < exploration.hp {
+        Some("You are hurt and stop exploring.".to_string())
+    } else if let Some(object) = found {
+        if object.item.is_some() {
+            Some(format!("You come across {}.", object.indefinite_name()))
+        } else {
+            Some(format!("You come across the {}.", object.name))
+        }
+    } else {
+        None
+    };
+    if let Some(interruption) = interruption {
+        game.log.add(interruption, colors::WHITE);
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    let (x, y) = objects[PLAYER].pos();
+    let mut distances = DijkstraMap::to_unexplored(&game.map);
+    if distances.value(x, y).is_none() {
+        distances = DijkstraMap::to_stairs(&game.map, objects);
+    }
+    match distances.downhill(x, y, |x, y| !is_blocked(x, y, &game.map, objects)) {
+        Some((next_x, next_y)) => {
+            move_by(PLAYER, next_x - x, next_y - y, &game.map, objects);
+            exploration.hp = hp;
+            game.exploring = Some(exploration);
+            PlayerAction::TookTurn
+        }
+        None => {
+            game.log
+                .add("There is nothing left to explore here.", colors::WHITE);
+            PlayerAction::DidntTakeTurn
+        }
+    }
+}
+
 fn handle_keys(
     key: input::Key,
     tcod: &mut Tcod,
@@ -613,38 +2305,63 @@ fn handle_keys(
     let player_alive = objects[PLAYER].alive;
     match (key, player_alive) {
         (Key { code: Up, .. }, true) | (Key { code: NumPad8, .. }, true) => {
-            player_move_or_attack(0, -1, game, objects);
+            player_move_or_attack(0, -1, game, objects, &tcod.fov);
             TookTurn
         }
         (Key { code: Down, .. }, true) | (Key { code: NumPad2, .. }, true) => {
-            player_move_or_attack(0, 1, game, objects);
+            player_move_or_attack(0, 1, game, objects, &tcod.fov);
             TookTurn
         }
         (Key { code: Left, .. }, true) | (Key { code: NumPad4, .. }, true) => {
-            player_move_or_attack(-1, 0, game, objects);
+            player_move_or_attack(-1, 0, game, objects, &tcod.fov);
             TookTurn
         }
         (Key { code: Right, .. }, true) | (Key { code: NumPad6, .. }, true) => {
-            player_move_or_attack(1, 0, game, objects);
+            player_move_or_attack(1, 0, game, objects, &tcod.fov);
             TookTurn
         }
         (Key { code: Home, .. }, true) => {
-            player_move_or_attack(-1, -1, game, objects);
+            player_move_or_attack(-1, -1, game, objects, &tcod.fov);
             TookTurn
         }
         (Key { code: PageUp, .. }, true) => {
-            player_move_or_attack(1, -1, game, objects);
+            player_move_or_attack(1, -1, game, objects, &tcod.fov);
             TookTurn
         }
         (Key { code: End, .. }, true) => {
-            player_move_or_attack(-1, 1, game, objects);
+            player_move_or_attack(-1, 1, game, objects, &tcod.fov);
             TookTurn
         }
         (Key { code: PageDown, .. }, true) => {
-            player_move_or_attack(1, 1, game, objects);
+            player_move_or_attack(1, 1, game, objects, &tcod.fov);
             TookTurn
         }
         (Key { printable: ' ', .. }, true) | (Key { code: NumPad5, .. }, true) => TookTurn,
+        (Key { printable: 'x', .. }, true) => start_auto_explore(game, objects, tcod),
+        (Key { printable: 't', .. }, true) => {
+            let inventory_index = inventory_menu(
+                &game.inventory,
+                "Press the key next to an item to throw it, or any other to cancel.\n",
+                &mut tcod.root,
+            );
+            match inventory_index {
+                Some(inventory_index) if throw_item(inventory_index, tcod, objects, game) => {
+                    TookTurn
+                }
+                _ => DidntTakeTurn,
+            }
+        }
+        (Key { printable: 'f', .. }, true) => {
+            if fire(tcod, objects, game) {
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+        (Key { printable: 'o', .. }, true) => {
+            give_orders(tcod, game, objects);
+            DidntTakeTurn
+        }
         (Key { printable: 'g', .. }, true) => {
             let item_id = objects
                 .iter()
@@ -665,6 +2382,18 @@ fn handle_keys(
             }
             TookTurn
         }
+        (Key { printable: 'w', .. }, true) => {
+            let inventory_index = inventory_menu(
+                &game.inventory,
+                "Press the key next to a weapon to wield it in your off hand, or any other to \
+                 cancel.\n",
+                &mut tcod.root,
+            );
+            match inventory_index {
+                Some(inventory_index) if wield_in_off_hand(inventory_index, game) => TookTurn,
+                _ => DidntTakeTurn,
+            }
+        }
         (Key { printable: 'd', .. }, true) => {
             let inventory_index = inventory_menu(
                 &game.inventory,
@@ -672,7 +2401,7 @@ fn handle_keys(
                 &mut tcod.root,
             );
             if let Some(inventory_index) = inventory_index {
-                drop_item(inventory_index, game, objects);
+                drop_item(inventory_index, game, objects, &mut tcod.root);
             }
             DidntTakeTurn
         }
@@ -685,6 +2414,19 @@ fn handle_keys(
             }
             DidntTakeTurn
         }
+        (Key { printable: 'e', .. }, true) => {
+            match equipment_menu(&game.inventory, &mut tcod.root) {
+                Some(inventory_index) => {
+                    game.inventory[inventory_index].dequip(&mut game.log);
+                    TookTurn
+                }
+                None => DidntTakeTurn,
+            }
+        }
+        (Key { printable: 'b', .. }, true) => {
+            combat_breakdown(game, &mut tcod.root);
+            DidntTakeTurn
+        }
         (Key { printable: 'c', .. }, true) => {
             let player = &objects[PLAYER];
             let level = player.level;
@@ -699,12 +2441,18 @@ Experience to level up: {}
 
 Maximum HP: {}
 Attack: {}
+Damage: {}
+Accuracy: {}
+Evasion: {}
 Defense: {}",
                     level,
                     fighter.xp,
                     level_up_xp,
                     player.max_hp(game),
                     player.power(game),
+                    player.damage_dice(game),
+                    player.accuracy(),
+                    player.evasion(),
                     player.defense(game),
                 );
                 msgbox(&msg, 30, &mut tcod.root);
@@ -769,8 +2517,8 @@ fn main_menu(tcod: &mut Tcod) {
                     initialize_fov(&game.map, tcod);
                     play_game(&mut objects, &mut game, tcod);
                 }
-                Err(_e) => {
-                    msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
+                Err(e) => {
+                    msgbox(&format!("\n{}\n", e), 36, &mut tcod.root);
                     continue;
                 }
             },
@@ -796,10 +2544,121 @@ fn next_level(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) {
         colors::RED,
     );
     game.dungeon_level += 1;
-    game.map = make_map(objects, game.dungeon_level);
+
+    // Companions that were told to stay behind are left on the old level
+    let followers: Vec<_> = objects
+        .drain(PLAYER + 1..)
+        .filter(|object| {
+            object.companion && object.ai.as_ref().and_then(Ai::order) != Some(Order::Stay)
+        })
+        .collect();
+    let (map, rooms) = make_map(objects, game.dungeon_level, &mut game.uniques);
+    game.map = map;
+    game.rooms = rooms;
+    game.identification.disguise_all(objects);
+    for follower in followers {
+        let name = follower.name.clone();
+        if !place_near_player(follower, &game.map, objects) {
+            game.log.add(
+                format!("There's no room for your {}, and it is left behind.", name),
+                colors::WHITE,
+            );
+        }
+    }
     initialize_fov(&game.map, tcod);
 }
 
+/// Put the object on a free tile as close to the player as possible. It is
+/// lost, and false returned, if there's no room anywhere nearby.
+fn place_near_player(mut object: Object, map: &Map, objects: &mut Vec<Object>) -> bool {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    for radius in 1..4 {
+        for dx in -radius..radius + 1 {
+            for dy in -radius..radius + 1 {
+                let (x, y) = (player_x + dx, player_y + dy);
+                let in_map = x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT;
+                if in_map && !is_blocked(x, y, map, objects) {
+                    object.set_pos(x, y);
+                    objects.push(object);
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+fn give_orders(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    let companions: Vec<_> = objects
+        .iter()
+        .enumerate()
+        .filter(|&(_, object)| object.companion && object.alive)
+        .map(|(id, _)| id)
+        .collect();
+    let companion_id = match companions.len() {
+        0 => {
+            game.log
+                .add("You have no companions to give orders to.", colors::WHITE);
+            return;
+        }
+        1 => companions[0],
+        _ => {
+            let names: Vec<_> = companions
+                .iter()
+                .map(|&id| objects[id].name.clone())
+                .collect();
+            match menu(
+                "Which companion?\n",
+                &names,
+                INVENTORY_WIDTH,
+                &mut tcod.root,
+            ) {
+                Some(index) => companions[index],
+                None => return,
+            }
+        }
+    };
+
+    let name = objects[companion_id].name.clone();
+    let choice = menu(
+        &format!("What should your {} do?\n", name),
+        &["Follow me", "Stay here", "Attack a target"],
+        INVENTORY_WIDTH,
+        &mut tcod.root,
+    );
+    let order = match choice {
+        Some(0) => Order::Follow,
+        Some(1) => Order::Stay,
+        Some(2) => {
+            game.log.add(
+                "Left-click an enemy to attack, or right-click to cancel.",
+                colors::LIGHT_CYAN,
+            );
+            match target_monster(tcod, objects, game, None) {
+                Some(target_id) if target_id != companion_id => Order::Attack {
+                    target: objects[target_id].pos(),
+                },
+                _ => return,
+            }
+        }
+        _ => return,
+    };
+
+    match objects[companion_id].ai {
+        Some(Ai::Companion {
+            order: ref mut current,
+        }) => {
+            *current = order;
+            game.log
+                .add(format!("Your {} obeys.", name), colors::LIGHT_GREEN);
+        }
+        _ => game.log.add(
+            format!("Your {} is in no state to listen.", name),
+            colors::WHITE,
+        ),
+    }
+}
+
 fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
     let player = &mut objects[PLAYER];
     let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
@@ -846,7 +2705,7 @@ fn level_up(objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
 }
 
 fn save_game(objects: &[Object], game: &Game) -> Result<(), Box<Error>> {
-    let save_data = serde_json::to_string(&(objects, game))?;
+    let save_data = serde_json::to_string(&(SAVE_VERSION, objects, game))?;
     let mut file = File::create("savegame")?;
     file.write_all(save_data.as_bytes())?;
     Ok(())
@@ -854,8 +2713,97 @@ fn save_game(objects: &[Object], game: &Game) -> Result<(), Box<Error>> {
 
 fn load_game() -> Result<(Vec<Object>, Game), Box<Error>> {
     let mut json_save_state = String::new();
-    let mut file = File::open("savegame")?;
+    let mut file = File::open("savegame").map_err(|_| "No saved game to load.")?;
     file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<(Vec<Object>, Game)>(&json_save_state)?;
-    Ok(result)
+    // Saves from before the version number was written start with the objects
+    let save: serde_json::Value = serde_json::from_str(&json_save_state)?;
+    if save.get(0).and_then(|version| version.as_u64()) != Some(SAVE_VERSION) {
+        return Err("That saved game is from an older version and can't be loaded.".into());
+    }
+    let (_, objects, game) = serde_json::from_value::<(u64, Vec<Object>, Game)>(save)?;
+    Ok((objects, game))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every kind of monster the game can ask `new_monster` for: those in
+    /// the spawn table, the members of packs and whatever their behaviours
+    /// summon.
+    fn monster_kinds() -> Vec<String> {
+        let mut kinds: Vec<String> = vec![];
+        for chance in monster_chances(1) {
+            match pack_members(chance.item) {
+                Some((_, members)) => kinds.extend(members.iter().map(|&kind| kind.into())),
+                None => kinds.push(chance.item.into()),
+            }
+        }
+        let mut checked = 0;
+        while checked < kinds.len() {
+            let kind = kinds[checked].clone();
+            checked += 1;
+            let monster = new_monster(&kind, 0, 0);
+            assert!(monster.is_some(), "no monster called {}", kind);
+            if let Some(Ai::Behaviour { ref name }) = monster.unwrap().ai {
+                for rule in behaviour::load(name).iter() {
+                    if let Action::Summon { ref kind, .. } = rule.action {
+                        if !kinds.contains(kind) {
+                            kinds.push(kind.clone());
+                        }
+                    }
+                }
+            }
+        }
+        kinds
+    }
+
+    #[test]
+    fn every_item_name_is_in_the_catalogue() {
+        let catalogue = catalogue::load();
+        let exists = |name: &str| catalogue.iter().any(|def| def.name == name);
+        assert!(
+            exists(STARTING_WEAPON),
+            "no starting weapon {}",
+            STARTING_WEAPON
+        );
+        let tables = [
+            LootTable::Orcs,
+            LootTable::Goblins,
+            LootTable::Casters,
+            LootTable::Trolls,
+        ];
+        for &table in &tables {
+            for chance in loot_chances(table, 1) {
+                if let Drop::Item(name) = chance.item {
+                    assert!(exists(name), "{:?} drops unknown item {}", table, name);
+                }
+            }
+        }
+        for kind in monster_kinds() {
+            if let Some(loot) = new_monster(&kind, 0, 0).and_then(|monster| monster.loot) {
+                for name in &loot.guaranteed {
+                    assert!(exists(name), "{} always drops unknown item {}", kind, name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_monster_behaviour_exists() {
+        for kind in monster_kinds() {
+            if let Some(Ai::Behaviour { ref name }) = new_monster(&kind, 0, 0).unwrap().ai {
+                let rules = behaviour::load(name);
+                assert!(!rules.is_empty(), "{} has no rules", kind);
+                for rule in rules.iter() {
+                    if let Action::Summon {
+                        kind: ref summoned, ..
+                    } = rule.action
+                    {
+                        assert!(!is_unique(summoned), "{} summons {}", kind, summoned);
+                    }
+                }
+            }
+        }
+    }
 }
@@ -1,25 +1,265 @@
 use constants::*;
+use faction::{self, Faction, Relation};
 use gui::*;
+use identify::{self, Identification};
+use projectile;
+use rand::Rng;
+use rng::GameRng;
+use spells::{self, KnownSpell};
+use status::{self, Effect, StatusEffect};
+use std::cmp;
 use std::fmt::*;
 use tcod::colors::{self, Color};
 use tcod::console::*;
 use tcod::input::Mouse;
 use tcod::map::Map as FovMap;
+use utils::{line, mut_two, plural};
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Equipment {
   pub slot: Slot,
+  #[serde(default)]
   pub equipped: bool,
+  #[serde(default)]
   pub power_bonus: i32,
+  #[serde(default)]
   pub defense_bonus: i32,
+  #[serde(default)]
   pub max_hp_bonus: i32,
+  /// Weapons roll these instead of the wielder's natural damage
+  pub damage: Option<Dice>,
+  #[serde(default)]
+  pub resistances: Resistances,
+  pub launcher: Option<Launcher>,
+  /// Two-handed weapons take up the off hand as well as the weapon hand
+  #[serde(default)]
+  pub two_handed: bool,
+  /// Percentage of the blows that hit the wearer a shield stops outright
+  #[serde(default)]
+  pub block_chance: i32,
 }
 
+impl Equipment {
+  /// Every slot the item takes up while it's equipped.
+  pub fn slots(&self) -> Vec<Slot> {
+    if self.two_handed {
+      vec![Slot::RightHand, Slot::LeftHand]
+    } else {
+      vec![self.slot]
+    }
+  }
+
+  /// One-handed weapons can also be wielded in the off hand.
+  pub fn fits_off_hand(&self) -> bool {
+    self.damage.is_some() && !self.two_handed
+  }
+
+  /// Where it's worn, as in "equipped on both hands".
+  pub fn place(&self) -> String {
+    if self.two_handed {
+      "both hands".into()
+    } else {
+      self.slot.to_string()
+    }
+  }
+
+  /// What the item does for its wearer, as in "1d6, +3 power".
+  pub fn stats(&self) -> String {
+    let mut stats = vec![];
+    if let Some(damage) = self.damage {
+      stats.push(damage.to_string());
+    }
+    if let Some(launcher) = self.launcher {
+      stats.push(format!(
+        "shoots {} for {}, range {}",
+        launcher.ammo.plural(),
+        launcher.damage,
+        launcher.range
+      ));
+    }
+    if self.power_bonus != 0 {
+      stats.push(format!("{:+} power", self.power_bonus));
+    }
+    if self.defense_bonus != 0 {
+      stats.push(format!("{:+} defense", self.defense_bonus));
+    }
+    if self.max_hp_bonus != 0 {
+      stats.push(format!("{:+} max HP", self.max_hp_bonus));
+    }
+    if self.block_chance != 0 {
+      stats.push(format!("{}% block", self.block_chance));
+    }
+    for &damage_type in &DamageType::all() {
+      let resistance = self.resistances.get(damage_type);
+      if resistance != 0 {
+        stats.push(format!("{}% {} resistance", resistance, damage_type));
+      }
+    }
+    stats.join(", ")
+  }
+}
+
+/// A bow, crossbow or sling. Its damage only applies to what it fires; in
+/// melee it's no better than bare hands.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Launcher {
+  pub ammo: AmmoKind,
+  pub range: i32,
+  pub damage: Dice,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AmmoKind {
+  Arrow,
+  Bolt,
+  Stone,
+}
+
+impl AmmoKind {
+  pub fn plural(self) -> &'static str {
+    match self {
+      AmmoKind::Arrow => "arrows",
+      AmmoKind::Bolt => "crossbow bolts",
+      AmmoKind::Stone => "sling stones",
+    }
+  }
+
+  pub fn verb(self) -> &'static str {
+    match self {
+      AmmoKind::Arrow => "shoots an arrow at",
+      AmmoKind::Bolt => "shoots a crossbow bolt at",
+      AmmoKind::Stone => "slings a stone at",
+    }
+  }
+}
+
+/// A pile of identical items that takes up a single inventory slot, such as
+/// a bundle of arrows or a few healing potions.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stack {
+  /// What one of them is called; the object's name is made from this
+  pub singular: String,
+  pub count: i32,
+}
+
+/// A damage roll such as 2d6: the total of `count` dice with `sides` sides.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dice {
+  pub count: i32,
+  pub sides: i32,
+}
+
+impl Dice {
+  pub fn roll<R: Rng>(&self, rng: &mut R) -> i32 {
+    (0..self.count)
+      .map(|_| rng.gen_range(1, self.sides + 1))
+      .sum()
+  }
+}
+
+impl Display for Dice {
+  fn fmt(&self, f: &mut Formatter) -> Result {
+    write!(f, "{}d{}", self.count, self.sides)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum DamageType {
+  Physical,
+  Fire,
+  Lightning,
+  Cold,
+  Poison,
+}
+
+impl DamageType {
+  pub fn all() -> [DamageType; 5] {
+    [
+      DamageType::Physical,
+      DamageType::Fire,
+      DamageType::Lightning,
+      DamageType::Cold,
+      DamageType::Poison,
+    ]
+  }
+}
+
+impl Display for DamageType {
+  fn fmt(&self, f: &mut Formatter) -> Result {
+    match *self {
+      DamageType::Physical => write!(f, "physical"),
+      DamageType::Fire => write!(f, "fire"),
+      DamageType::Lightning => write!(f, "lightning"),
+      DamageType::Cold => write!(f, "cold"),
+      DamageType::Poison => write!(f, "poison"),
+    }
+  }
+}
+
+/// How much of each type of damage is shrugged off, in percent. Negative
+/// values are vulnerabilities that make the damage hurt more.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Resistances {
+  pub physical: i32,
+  pub fire: i32,
+  pub lightning: i32,
+  pub cold: i32,
+  pub poison: i32,
+}
+
+impl Resistances {
+  pub fn get(&self, damage_type: DamageType) -> i32 {
+    match damage_type {
+      DamageType::Physical => self.physical,
+      DamageType::Fire => self.fire,
+      DamageType::Lightning => self.lightning,
+      DamageType::Cold => self.cold,
+      DamageType::Poison => self.poison,
+    }
+  }
+}
+
+/// The right hand is the weapon hand; a weapon in the left hand is wielded
+/// alongside it, at a penalty. A ring can go on either finger.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Slot {
   LeftHand,
   RightHand,
   Head,
+  Neck,
+  Back,
+  Body,
+  Hands,
+  LeftFinger,
+  RightFinger,
+  Feet,
+}
+
+impl Slot {
+  /// Every slot, from head to toe.
+  pub fn all() -> [Slot; 10] {
+    [
+      Slot::Head,
+      Slot::Neck,
+      Slot::Back,
+      Slot::Body,
+      Slot::RightHand,
+      Slot::LeftHand,
+      Slot::Hands,
+      Slot::RightFinger,
+      Slot::LeftFinger,
+      Slot::Feet,
+    ]
+  }
+
+  pub fn other_finger(self) -> Option<Slot> {
+    match self {
+      Slot::LeftFinger => Some(Slot::RightFinger),
+      Slot::RightFinger => Some(Slot::LeftFinger),
+      _ => None,
+    }
+  }
 }
 
 impl Display for Slot {
@@ -28,16 +268,157 @@ impl Display for Slot {
       Slot::LeftHand => write!(f, "left hand"),
       Slot::RightHand => write!(f, "right hand"),
       Slot::Head => write!(f, "head"),
+      Slot::Neck => write!(f, "neck"),
+      Slot::Back => write!(f, "back"),
+      Slot::Body => write!(f, "body"),
+      Slot::Hands => write!(f, "hands"),
+      Slot::LeftFinger => write!(f, "left finger"),
+      Slot::RightFinger => write!(f, "right finger"),
+      Slot::Feet => write!(f, "feet"),
     }
   }
 }
 
+/// What the player was up against when they set off exploring on their
+/// own, so that anything new can stop them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exploration {
+  pub hp: i32,
+  /// Items and stairs that were already in view
+  pub seen: Vec<(i32, i32)>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Game {
   pub map: Map,
   pub log: Messages,
   pub inventory: Vec<Object>,
   pub dungeon_level: u32,
+  /// Turns the rest of the world has taken
+  #[serde(default)]
+  pub turn: u32,
+  /// Set once a hasted player has had the first of their two moves
+  #[serde(default)]
+  pub player_bonus_action: bool,
+  /// Where creatures died this turn, and whose side they were on
+  #[serde(default)]
+  pub recent_deaths: Vec<((i32, i32), Faction)>,
+  /// Centres of the rooms on this level, for monsters to wander between
+  #[serde(default)]
+  pub rooms: Vec<(i32, i32)>,
+  #[serde(default)]
+  pub gold: i32,
+  /// Unique monsters that have already appeared, so none of them turns up twice
+  #[serde(default)]
+  pub uniques: Vec<String>,
+  /// The most recent attacks, oldest first
+  #[serde(default)]
+  pub attacks: Vec<AttackRecord>,
+  /// Damage the player dealt and took, one entry per dungeon level visited
+  #[serde(default)]
+  pub damage_stats: Vec<DamageStats>,
+  #[serde(default)]
+  pub identification: Identification,
+  /// Seeded once per game, so that the same seed plays out the same way
+  #[serde(default)]
+  pub rng: GameRng,
+  /// Set while the player is exploring on their own
+  #[serde(skip)]
+  pub exploring: Option<Exploration>,
+}
+
+impl Game {
+  pub fn record_attack(&mut self, record: AttackRecord) {
+    self.attacks.push(record);
+    if self.attacks.len() > ATTACK_RECORDS_KEPT {
+      self.attacks.remove(0);
+    }
+  }
+
+  /// The damage stats for the current level, started the first time
+  /// anything happens on it.
+  pub fn level_stats(&mut self) -> &mut DamageStats {
+    let level = self.dungeon_level;
+    if self
+      .damage_stats
+      .last()
+      .is_none_or(|stats| stats.level != level)
+    {
+      self.damage_stats.push(DamageStats {
+        level,
+        dealt: 0,
+        taken: 0,
+      });
+    }
+    self.damage_stats.last_mut().unwrap()
+  }
+
+  /// Count damage towards the player's stats when they dealt or took it.
+  pub fn record_damage(&mut self, attacker_id: usize, target_id: usize, damage: i32) {
+    if damage <= 0 {
+      return;
+    }
+    if target_id == PLAYER {
+      self.level_stats().taken += damage;
+    } else if attacker_id == PLAYER {
+      self.level_stats().dealt += damage;
+    }
+  }
+}
+
+/// What came of an attack: the damage it did, and the experience it earned
+/// if it was a killing blow.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Blow {
+  pub damage: i32,
+  pub xp: i32,
+}
+
+/// How a blow is delivered. `verb` says how, as in "shoots an arrow at",
+/// and `penalty` how much harder range or the off hand made it to land.
+#[derive(Clone, Copy, Debug)]
+pub struct Strike<'a> {
+  pub verb: &'a str,
+  pub dice: Dice,
+  pub damage_type: DamageType,
+  pub penalty: i32,
+}
+
+/// Everything that went into one attack, kept so the numbers can be checked
+/// on the combat breakdown screen.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AttackRecord {
+  pub turn: u32,
+  pub attacker: String,
+  pub target: String,
+  pub verb: String,
+  pub accuracy: i32,
+  pub evasion: i32,
+  /// Taken off the chance to hit for range or the off hand
+  pub penalty: i32,
+  pub hit_chance: i32,
+  pub to_hit: i32,
+  pub hit: bool,
+  pub blocked: bool,
+  pub critical: bool,
+  pub dice: Dice,
+  pub dice_roll: i32,
+  pub base_power: i32,
+  pub equipment_power: i32,
+  /// Power from status effects such as strength
+  pub effect_power: i32,
+  pub defense: i32,
+  pub absorbed: i32,
+  pub resistance: i32,
+  pub damage_type: DamageType,
+  pub damage: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DamageStats {
+  pub level: u32,
+  pub dealt: i32,
+  pub taken: i32,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -81,21 +462,178 @@ pub struct Fighter {
   pub hp: i32,
   pub base_defense: i32,
   pub base_power: i32,
+  pub base_accuracy: i32,
+  pub base_evasion: i32,
+  /// Natural damage, used when no weapon is wielded
+  pub damage: Dice,
+  pub damage_type: DamageType,
   pub base_max_hp: i32,
   pub xp: i32,
   pub on_death: DeathCallback,
 }
 
+/// How much punishment a monster takes before it runs away. Courage drops as
+/// the monster gets hurt or sees its allies die, and it flees once courage is
+/// below `flee_below`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Morale {
+  pub courage: i32,
+  pub max_courage: i32,
+  pub flee_below: i32,
+}
+
+impl Morale {
+  /// Courage never drops below nothing, so a monster can always recover.
+  pub fn lose(&mut self, courage: i32) {
+    self.courage = cmp::max(self.courage - courage, 0);
+  }
+
+  pub fn is_broken(&self) -> bool {
+    self.courage < self.flee_below
+  }
+
+  /// Fleeing monsters come back once they're halfway between breaking and
+  /// full courage, so they don't flip-flop at the threshold.
+  pub fn has_recovered(&self) -> bool {
+    self.courage >= (self.flee_below + self.max_courage) / 2
+  }
+}
+
+/// A monster's attack from a distance. It deals the same damage as the
+/// monster's melee attack, but only needs a clear line of fire.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RangedAttack {
+  pub missile: Missile,
+  pub range: i32,
+  /// How far away from its target the monster tries to stay
+  pub preferred_distance: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Missile {
+  Arrow,
+  FrostBolt,
+}
+
+impl Missile {
+  pub fn verb(self) -> &'static str {
+    match self {
+      Missile::Arrow => "shoots an arrow at",
+      Missile::FrostBolt => "hurls a frost bolt at",
+    }
+  }
+
+  pub fn damage_type(self) -> DamageType {
+    match self {
+      Missile::Arrow => DamageType::Physical,
+      Missile::FrostBolt => DamageType::Cold,
+    }
+  }
+}
+
+/// What a monster leaves behind when it dies.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Loot {
+  pub table: LootTable,
+  /// How many times to roll on the table
+  pub rolls: i32,
+  /// Catalogue names of the items dropped every time, on top of whatever
+  /// the rolls give
+  pub guaranteed: Vec<String>,
+}
+
+/// The loot tables monsters roll on. What's in them, and how likely it is
+/// at each depth, is set up in `loot_chances`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LootTable {
+  Orcs,
+  Goblins,
+  Casters,
+  Trolls,
+}
+
+/// Membership of a group of monsters that spawned together and hunt as one.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PackMember {
+  pub pack_id: usize,
+  pub tactics: PackTactics,
+  pub leader: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum PackTactics {
+  /// Spread out around the prey, and wait for it to leave corridors
+  Surround,
+  /// Stick with the leader, and fall apart once it dies
+  WarBand,
+}
+
+/// What happens when an item is used, along with how strong the effect is.
+/// Which items do what is set out in the item catalogue.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Item {
-  Heal,
-  Lightning,
-  Confuse,
-  Fireball,
-  Sword,
-  Shield,
+  Heal {
+    amount: i32,
+  },
+  Lightning {
+    damage: i32,
+    range: i32,
+  },
+  Confuse {
+    range: i32,
+    turns: i32,
+  },
+  Charm {
+    range: i32,
+  },
+  Fireball {
+    damage: i32,
+    radius: i32,
+  },
+  MagicMapping,
+  Telepathy {
+    turns: i32,
+  },
+  DetectObjects,
+  Regeneration {
+    turns: i32,
+    amount: i32,
+  },
+  Haste {
+    turns: i32,
+  },
+  Strength {
+    turns: i32,
+    bonus: i32,
+  },
+  Blindness {
+    turns: i32,
+  },
+  Slow {
+    range: i32,
+    turns: i32,
+  },
+  Paralyse {
+    range: i32,
+    turns: i32,
+  },
+  Identify,
+  Dig {
+    range: i32,
+  },
+  /// Put this many charges back into a wand or staff
+  Recharge {
+    charges: i32,
+  },
+  /// Worn or wielded; what it does is up to its `Equipment`
+  Equip,
+  /// Readied for the matching launcher
+  Ammo,
+  Gold,
+  Corpse,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum UseResult {
   UsedUp,
   Cancelled,
@@ -106,6 +644,7 @@ enum UseResult {
 pub enum DeathCallback {
   Player,
   Monster,
+  Companion,
 }
 
 impl DeathCallback {
@@ -114,6 +653,7 @@ impl DeathCallback {
     let callback: fn(&mut Object, &mut Game) = match self {
       Player => player_death,
       Monster => monster_death,
+      Companion => companion_death,
     };
     callback(object, game);
   }
@@ -122,13 +662,54 @@ impl DeathCallback {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Ai {
   Basic,
-  Confused {
+  Ranged,
+  Pack,
+  /// Driven by the named behaviour from the content file
+  Behaviour {
+    name: String,
+  },
+  Companion {
+    order: Order,
+  },
+  Fleeing {
     previous_ai: Box<Ai>,
-    num_turns: i32,
   },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Ai {
+  /// The orders a companion is under, even while it runs away.
+  pub fn order(&self) -> Option<Order> {
+    match *self {
+      Ai::Companion { order } => Some(order),
+      Ai::Fleeing { ref previous_ai } => previous_ai.order(),
+      _ => None,
+    }
+  }
+}
+
+/// What the player has told a companion to do.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Order {
+  Follow,
+  Stay,
+  /// Attack the creature last seen at this position
+  Attack {
+    target: (i32, i32),
+  },
+}
+
+/// What a monster does while it has nothing to hunt.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Idle {
+  /// Stroll from room to room
+  Wander { goal: Option<(i32, i32)> },
+  /// Walk the same route over and over
+  Patrol { route: Vec<(i32, i32)>, next: usize },
+  /// Keep watch over a spot, and go back to it after a chase
+  Guard { post: (i32, i32) },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Object {
   pub x: i32,
   pub y: i32,
@@ -136,13 +717,54 @@ pub struct Object {
   pub blocks: bool,
   pub alive: bool,
   pub always_visible: bool,
+  #[serde(default)]
+  pub detected: bool,
+  #[serde(default)]
+  pub companion: bool,
+  #[serde(default)]
+  pub faction: Option<Faction>,
   pub char: char,
   pub color: Color,
   pub level: i32,
   pub fighter: Option<Fighter>,
   pub ai: Option<Ai>,
+  #[serde(default)]
+  pub morale: Option<Morale>,
+  #[serde(default)]
+  pub ranged: Option<RangedAttack>,
+  #[serde(default)]
+  pub spells: Vec<KnownSpell>,
+  #[serde(default)]
+  pub pack: Option<PackMember>,
+  #[serde(default)]
+  pub idle: Option<Idle>,
+  #[serde(default)]
+  pub last_seen_player: Option<(i32, i32)>,
+  #[serde(default)]
+  pub resistances: Resistances,
+  #[serde(default)]
+  pub effects: Vec<StatusEffect>,
   pub item: Option<Item>,
+  /// The catalogue entry the item was made from
+  #[serde(default)]
+  pub kind: Option<String>,
   pub equipment: Option<Equipment>,
+  /// Ammunition is fired by a launcher that shoots its kind
+  #[serde(default)]
+  pub ammo: Option<AmmoKind>,
+  #[serde(default)]
+  pub stack: Option<Stack>,
+  /// Uses left in a wand or staff
+  #[serde(default)]
+  pub charges: Option<i32>,
+  #[serde(default)]
+  pub loot: Option<Loot>,
+  /// How many gold pieces a pile of gold is worth
+  #[serde(default)]
+  pub gold: Option<i32>,
+  /// Turns left until a corpse rots away
+  #[serde(default)]
+  pub decay: Option<i32>,
 }
 
 impl Object {
@@ -156,11 +778,29 @@ impl Object {
       blocks: blocks,
       alive: false,
       always_visible: false,
+      detected: false,
+      companion: false,
+      faction: None,
       level: 1,
       fighter: None,
       ai: None,
+      morale: None,
+      ranged: None,
+      spells: vec![],
+      pack: None,
+      idle: None,
+      last_seen_player: None,
+      resistances: Resistances::default(),
+      effects: vec![],
       item: None,
+      kind: None,
       equipment: None,
+      ammo: None,
+      stack: None,
+      charges: None,
+      loot: None,
+      gold: None,
+      decay: None,
     }
   }
 
@@ -182,6 +822,17 @@ impl Object {
     self.y = y;
   }
 
+  pub fn relation_to(&self, other: &Object) -> Relation {
+    match (self.faction, other.faction) {
+      (Some(a), Some(b)) => faction::relation(a, b),
+      _ => Relation::Neutral,
+    }
+  }
+
+  pub fn is_hostile_to(&self, other: &Object) -> bool {
+    self.relation_to(other) == Relation::Hostile
+  }
+
   pub fn distance_to(&self, other: &Object) -> f32 {
     let dx = other.x - self.x;
     let dy = other.y - self.y;
@@ -199,11 +850,74 @@ impl Object {
 
   pub fn power(&self, game: &Game) -> i32 {
     let base_power = self.fighter.map_or(0, |f| f.base_power);
-    let bonus = self
+    base_power + self.equipment_power(game) + self.effect_magnitude(Effect::Strength)
+  }
+
+  pub fn equipment_power(&self, game: &Game) -> i32 {
+    self
       .get_all_equipped(game)
       .iter()
-      .fold(0, |sum, e| sum + e.power_bonus);
-    base_power + bonus
+      .fold(0, |sum, e| sum + e.power_bonus)
+  }
+
+  pub fn has_effect(&self, effect: Effect) -> bool {
+    self.effects.iter().any(|status| status.effect == effect)
+  }
+
+  pub fn effect_magnitude(&self, effect: Effect) -> i32 {
+    self
+      .effects
+      .iter()
+      .filter(|status| status.effect == effect)
+      .fold(0, |sum, status| sum + status.magnitude)
+  }
+
+  /// How many items there are in this object: more than one for a stack.
+  pub fn count(&self) -> i32 {
+    self.stack.as_ref().map_or(1, |stack| stack.count)
+  }
+
+  /// Change how many items are in this stack, renaming it to match, as in
+  /// "3 healing potions".
+  pub fn set_count(&mut self, count: i32) {
+    if let Some(ref mut stack) = self.stack {
+      stack.count = count;
+      self.name = if count == 1 {
+        stack.singular.clone()
+      } else {
+        format!("{} {}", count, plural(&stack.singular))
+      };
+    }
+  }
+
+  /// Whether the two are piles of the same thing, that can be merged.
+  pub fn stacks_with(&self, other: &Object) -> bool {
+    match (&self.stack, &other.stack) {
+      (Some(mine), Some(theirs)) => {
+        mine.singular == theirs.singular && self.item == other.item && self.ammo == other.ammo
+      }
+      _ => false,
+    }
+  }
+
+  /// Take `count` items off this stack, as a stack of their own.
+  pub fn split_off(&mut self, count: i32) -> Object {
+    let mut split = self.clone();
+    split.set_count(count);
+    let left = self.count() - count;
+    self.set_count(left);
+    split
+  }
+
+  /// The name with "a" or "an" in front, unless there are several.
+  pub fn indefinite_name(&self) -> String {
+    if self.count() > 1 {
+      self.name.clone()
+    } else if self.name.starts_with(|c| "aeiou".contains(c)) {
+      format!("an {}", self.name)
+    } else {
+      format!("a {}", self.name)
+    }
   }
 
   pub fn defense(&self, game: &Game) -> i32 {
@@ -215,12 +929,82 @@ impl Object {
     base_defense + bonus
   }
 
+  pub fn accuracy(&self) -> i32 {
+    self.fighter.map_or(0, |f| f.base_accuracy)
+  }
+
+  pub fn evasion(&self) -> i32 {
+    self.fighter.map_or(0, |f| f.base_evasion)
+  }
+
+  /// The weapon hand's damage dice, or the creature's natural ones when it
+  /// has nothing there to hit with.
+  pub fn damage_dice(&self, game: &Game) -> Dice {
+    let natural = self
+      .fighter
+      .map_or(Dice { count: 1, sides: 1 }, |f| f.damage);
+    self.weapons(game).first().cloned().unwrap_or(natural)
+  }
+
+  /// The damage dice of a second weapon held in the off hand, if any.
+  pub fn off_hand_weapon(&self, game: &Game) -> Option<Dice> {
+    self.weapons(game).get(1).cloned()
+  }
+
+  /// The damage dice of the weapons in hand, the weapon hand's first.
+  fn weapons(&self, game: &Game) -> Vec<Dice> {
+    let equipped = self.get_all_equipped(game);
+    [Slot::RightHand, Slot::LeftHand]
+      .iter()
+      .filter_map(|&hand| equipped.iter().find(|e| e.slot == hand))
+      .filter_map(|e| e.damage)
+      .collect()
+  }
+
+  pub fn block_chance(&self, game: &Game) -> i32 {
+    self
+      .get_all_equipped(game)
+      .iter()
+      .fold(0, |sum, e| sum + e.block_chance)
+  }
+
+  /// The creature's total resistance to a damage type, counting its
+  /// equipment. Nothing resists more than all of it.
+  pub fn resistance(&self, damage_type: DamageType, game: &Game) -> i32 {
+    let bonus = self
+      .get_all_equipped(game)
+      .iter()
+      .fold(0, |sum, e| sum + e.resistances.get(damage_type));
+    cmp::min(self.resistances.get(damage_type) + bonus, 100)
+  }
+
+  /// How much of `damage` gets through this creature's resistance.
+  pub fn resist(&self, damage: i32, damage_type: DamageType, game: &Game) -> i32 {
+    damage * (100 - self.resistance(damage_type, game)) / 100
+  }
+
+  /// Weapons deal physical damage; unarmed creatures hit with their own kind.
+  pub fn attack_type(&self, game: &Game) -> DamageType {
+    let armed = !self.weapons(game).is_empty();
+    match self.fighter {
+      Some(fighter) if !armed => fighter.damage_type,
+      _ => DamageType::Physical,
+    }
+  }
+
   pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
+    let max_hp = self.max_hp(game);
     if let Some(fighter) = self.fighter.as_mut() {
       if damage > 0 {
         fighter.hp -= damage;
       }
     }
+    if let Some(morale) = self.morale.as_mut() {
+      if damage > 0 && max_hp > 0 {
+        // Losing a share of its health costs the same share of its courage
+        morale.lose(damage * morale.max_courage / max_hp);
+      }
+    }
 
     if let Some(fighter) = self.fighter {
       if fighter.hp <= 0 {
@@ -232,33 +1016,195 @@ impl Object {
     None
   }
 
-  pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
-    let damage = self.power(game) - target.defense(game);
-    if damage > 0 {
-      game.log.add(
-        format!(
-          "{} attacks {} for {} hit points.",
-          self.name, target.name, damage
-        ),
-        colors::DESATURATED_FUCHSIA,
-      );
-      if let Some(xp) = target.take_damage(damage, game) {
-        self.fighter.as_mut().unwrap().xp += xp;
+  /// Attack with the weapon hand, and follow up with the off hand when
+  /// wielding two weapons. Both blows are harder to land that way.
+  pub fn attack(&mut self, target: &mut Object, witnessed: bool, game: &mut Game) -> Blow {
+    let off_hand = self.off_hand_weapon(game);
+    let strike = Strike {
+      verb: "attacks",
+      dice: self.damage_dice(game),
+      damage_type: self.attack_type(game),
+      penalty: if off_hand.is_some() {
+        DUAL_WIELD_PENALTY
+      } else {
+        0
+      },
+    };
+    let mut blow = self.hit(target, strike, witnessed, game);
+    if let Some(dice) = off_hand {
+      if target.alive {
+        let strike = Strike {
+          verb: "also strikes",
+          dice,
+          damage_type: DamageType::Physical,
+          penalty: OFF_HAND_PENALTY,
+        };
+        let follow_up = self.hit(target, strike, witnessed, game);
+        blow.damage += follow_up.damage;
+        blow.xp += follow_up.xp;
       }
+    }
+    blow
+  }
+
+  /// Roll an attack on the target. Melee and ranged attacks both go through
+  /// here. Accuracy against evasion, less the strike's penalty, decides
+  /// whether it hits, and a shield may still block it. The damage dice plus
+  /// power say how hard it hits, and armour soaks up part of that but never
+  /// all of it. Resistances come last. Only attacks the player `witnessed`
+  /// are logged and kept for the combat screen.
+  pub fn hit(
+    &mut self,
+    target: &mut Object,
+    strike: Strike,
+    witnessed: bool,
+    game: &mut Game,
+  ) -> Blow {
+    let Strike {
+      verb,
+      dice,
+      damage_type,
+      penalty,
+    } = strike;
+    let mut record = AttackRecord {
+      turn: game.turn,
+      attacker: self.name.clone(),
+      target: target.name.clone(),
+      verb: verb.into(),
+      accuracy: self.accuracy(),
+      evasion: target.evasion(),
+      penalty,
+      hit_chance: 0,
+      to_hit: 0,
+      hit: false,
+      blocked: false,
+      critical: false,
+      dice,
+      dice_roll: 0,
+      base_power: self.fighter.map_or(0, |f| f.base_power),
+      equipment_power: self.equipment_power(game),
+      effect_power: self.effect_magnitude(Effect::Strength),
+      defense: target.defense(game),
+      absorbed: 0,
+      resistance: target.resistance(damage_type, game),
+      damage_type,
+      damage: 0,
+    };
+    let hit_chance =
+      BASE_HIT_CHANCE + HIT_CHANCE_PER_POINT * (record.accuracy - record.evasion) - penalty;
+    record.hit_chance = hit_chance.clamp(MIN_HIT_CHANCE, MAX_HIT_CHANCE);
+    record.to_hit = game.rng.gen_range(1, 101);
+    let penalised = if penalty > 0 {
+      format!(" with a -{}% penalty", penalty)
     } else {
+      String::new()
+    };
+    if record.to_hit > record.hit_chance {
+      if witnessed {
+        game.log.add(
+          format!("{} {} {} but misses.", self.name, verb, target.name),
+          colors::DESATURATED_FUCHSIA,
+        );
+        game.log.add(
+          format!(
+            "  (rolled {} against {}% to hit{})",
+            record.to_hit, record.hit_chance, penalised
+          ),
+          colors::GREY,
+        );
+        game.record_attack(record);
+      }
+      return Blow::default();
+    }
+
+    record.hit = true;
+    if game.rng.gen_range(0, 100) < target.block_chance(game) {
+      record.blocked = true;
+      if witnessed {
+        game.log.add(
+          format!(
+            "{} {} {} but it's blocked by a shield.",
+            self.name, verb, target.name
+          ),
+          colors::DESATURATED_FUCHSIA,
+        );
+        game.log.add(
+          format!(
+            "  (rolled {} against {}% to hit{}, then blocked)",
+            record.to_hit, record.hit_chance, penalised
+          ),
+          colors::GREY,
+        );
+        game.record_attack(record);
+      }
+      return Blow::default();
+    }
+    record.critical = game.rng.gen_range(0, 100) < CRITICAL_CHANCE;
+    if record.critical {
+      record.dice.count *= 2;
+    }
+    record.dice_roll = record.dice.roll(&mut game.rng);
+    let power = record.base_power + record.equipment_power + record.effect_power;
+    let rolled = record.dice_roll + power;
+    if record.defense > 0 {
+      record.absorbed = game.rng.gen_range(0, record.defense + 1);
+    }
+    let armoured = cmp::max(rolled - record.absorbed, 1);
+    let damage = target.resist(armoured, damage_type, game);
+    record.damage = damage;
+
+    if witnessed {
+      if record.critical {
+        game.log.add(
+          format!(
+            "{} {} {} with a critical hit for {} hit points!",
+            self.name, verb, target.name, damage
+          ),
+          colors::LIGHT_FUCHSIA,
+        );
+      } else {
+        game.log.add(
+          format!(
+            "{} {} {} for {} hit points.",
+            self.name, verb, target.name, damage
+          ),
+          colors::DESATURATED_FUCHSIA,
+        );
+      }
+      let resisted = if record.resistance != 0 {
+        format!(", {}% {} resistance", record.resistance, damage_type)
+      } else {
+        String::new()
+      };
       game.log.add(
         format!(
-          "{} attacks {} but it has no effect!",
-          self.name, target.name
+          "  (rolled {} against {}% to hit{}, {}{:+} = {} {} damage, {} absorbed{})",
+          record.to_hit,
+          record.hit_chance,
+          penalised,
+          record.dice,
+          power,
+          rolled,
+          damage_type,
+          record.absorbed,
+          resisted
         ),
-        colors::DESATURATED_FUCHSIA,
+        colors::GREY,
       );
+      game.record_attack(record);
+    }
+    if damage_type == DamageType::Poison && damage > 0 && target.alive {
+      status::apply(target, Effect::Poison, POISON_NUM_TURNS, POISON_DAMAGE);
+    }
+    Blow {
+      damage,
+      xp: target.take_damage(damage, game).unwrap_or(0),
     }
   }
 
   pub fn heal(&mut self, amount: i32, game: &Game) {
     let max_hp = self.max_hp(game);
-    if let Some(mut fighter) = self.fighter {
+    if let Some(ref mut fighter) = self.fighter {
       fighter.hp += amount;
       if fighter.hp > max_hp {
         fighter.hp = max_hp;
@@ -295,7 +1241,7 @@ impl Object {
       if !equipment.equipped {
         equipment.equipped = true;
         log.add(
-          format!("Equipped {} on {}.", self.name, equipment.slot),
+          format!("Equipped {} on {}.", self.name, equipment.place()),
           colors::LIGHT_GREEN,
         );
       }
@@ -319,9 +1265,13 @@ impl Object {
       if equipment.equipped {
         equipment.equipped = false;
         log.add(
-          format!("Dequipped {} from {}.", self.name, equipment.slot),
+          format!("Dequipped {} from {}.", self.name, equipment.place()),
           colors::LIGHT_YELLOW,
         );
+        // Weapons only stay in the off hand while they're wielded there
+        if equipment.slot == Slot::LeftHand && equipment.fits_off_hand() {
+          equipment.slot = Slot::RightHand;
+        }
       }
     } else {
       log.add(
@@ -332,27 +1282,130 @@ impl Object {
   }
 }
 
-pub fn pick_item_up(object_id: usize, objects: &mut Vec<Object>, game: &mut Game) {
-  if game.inventory.len() >= 26 {
-    game.log.add(
-      format!(
-        "Your inventory is full, cannot pick up {}.",
-        objects[object_id].name
+/// One creature attacks another in melee.
+pub fn melee(
+  attacker_id: usize,
+  target_id: usize,
+  objects: &mut [Object],
+  game: &mut Game,
+  fov_map: &FovMap,
+) {
+  let witnessed = witnesses(attacker_id, target_id, objects, fov_map);
+  let (attacker, target) = mut_two(attacker_id, target_id, objects);
+  let blow = attacker.attack(target, witnessed, game);
+  settle_blow(attacker_id, target_id, blow, objects, game);
+}
+
+/// Whether the player sees a fight: they're in it, or at least one side of
+/// it is in view.
+pub fn witnesses(
+  attacker_id: usize,
+  target_id: usize,
+  objects: &[Object],
+  fov_map: &FovMap,
+) -> bool {
+  [attacker_id, target_id]
+    .iter()
+    .any(|&id| id == PLAYER || fov_map.is_in_fov(objects[id].x, objects[id].y))
+}
+
+/// Count a blow towards the player's damage stats if they were in on it,
+/// and give the attacker the experience for a kill.
+pub fn settle_blow(
+  attacker_id: usize,
+  target_id: usize,
+  blow: Blow,
+  objects: &mut [Object],
+  game: &mut Game,
+) {
+  game.record_damage(attacker_id, target_id, blow.damage);
+  if attacker_id != target_id {
+    gain_xp(attacker_id, blow.xp, objects, game);
+  }
+}
+
+/// Give a creature experience for a kill. Only the player hears about it.
+pub fn gain_xp(id: usize, xp: i32, objects: &mut [Object], game: &mut Game) {
+  if xp <= 0 {
+    return;
+  }
+  if let Some(fighter) = objects[id].fighter.as_mut() {
+    fighter.xp += xp;
+    if id == PLAYER {
+      game.log.add(
+        format!("You gain {} experience points.", xp),
+        colors::ORANGE,
+      );
+    }
+  }
+}
+
+/// Put an item on the floor at (x, y), on top of a pile of the same thing
+/// if one is already lying there.
+pub fn land(mut item: Object, (x, y): (i32, i32), objects: &mut Vec<Object>) {
+  let pile = objects
+    .iter()
+    .position(|object| object.pos() == (x, y) && object.stacks_with(&item));
+  match pile {
+    Some(pile) => {
+      let total = objects[pile].count() + item.count();
+      objects[pile].set_count(total);
+    }
+    None => {
+      item.set_pos(x, y);
+      objects.push(item);
+    }
+  }
+}
+
+pub fn pick_item_up(object_id: usize, objects: &mut Vec<Object>, game: &mut Game) {
+  // Gold goes straight into the purse
+  if let Some(gold) = objects[object_id].gold {
+    objects.swap_remove(object_id);
+    game.gold += gold;
+    game
+      .log
+      .add(format!("You picked up {} gold pieces.", gold), colors::GOLD);
+    return;
+  }
+
+  // Stacks go onto a stack of the same thing, if there is one
+  let stack = game
+    .inventory
+    .iter()
+    .position(|item| item.stacks_with(&objects[object_id]));
+  if let Some(stack) = stack {
+    let item = objects.swap_remove(object_id);
+    let total = game.inventory[stack].count() + item.count();
+    game.inventory[stack].set_count(total);
+    game.log.add(
+      format!("You picked up {}!", item.indefinite_name()),
+      colors::GREEN,
+    );
+    return;
+  }
+
+  if game.inventory.len() >= 26 {
+    game.log.add(
+      format!(
+        "Your inventory is full, cannot pick up {}.",
+        objects[object_id].name
       ),
       colors::RED,
     );
   } else {
     let item = objects.swap_remove(object_id);
-    game
-      .log
-      .add(format!("You picked up a {}!", item.name), colors::GREEN);
+    game.log.add(
+      format!("You picked up {}!", item.indefinite_name()),
+      colors::GREEN,
+    );
     let index = game.inventory.len();
-    let slot = item.equipment.map(|e| e.slot);
     game.inventory.push(item);
 
-    // Automatically equip, if the corresponding equipment slot is unused
-    if let Some(slot) = slot {
-      if get_equipped_in_slot(slot, &game.inventory).is_none() {
+    // Automatically equip, if the slots it needs are unused
+    find_free_finger(index, &mut game.inventory);
+    if let Some(equipment) = game.inventory[index].equipment {
+      if equipped_in_the_way(equipment, &game.inventory).is_empty() {
         game.inventory[index].equip(&mut game.log);
       }
     }
@@ -367,36 +1420,93 @@ fn player_death(player: &mut Object, game: &mut Game) {
 }
 
 fn monster_death(monster: &mut Object, game: &mut Game) {
-  game.log.add(
-    format!(
-      "{} is dead! You gain {} experience points.",
-      monster.name,
-      monster.fighter.unwrap().xp
-    ),
-    colors::ORANGE,
-  );
-  monster.char = '%';
-  monster.color = colors::DARK_RED;
-  monster.blocks = false;
-  monster.fighter = None;
-  monster.ai = None;
-  monster.name = format!("remains of {}", monster.name);
+  game
+    .log
+    .add(format!("{} is dead!", monster.name), colors::ORANGE);
+  if let Some(faction) = monster.faction {
+    game.recent_deaths.push((monster.pos(), faction));
+  }
+  leave_corpse(monster);
+}
+
+/// Turn a dead creature into a corpse that can be picked up, until it rots.
+fn leave_corpse(creature: &mut Object) {
+  creature.char = '%';
+  creature.color = colors::DARK_RED;
+  creature.blocks = false;
+  creature.fighter = None;
+  creature.ai = None;
+  creature.name = format!("{} corpse", creature.name);
+  creature.item = Some(Item::Corpse);
+  creature.decay = Some(CORPSE_DECAY_TURNS);
+}
+
+fn companion_death(companion: &mut Object, game: &mut Game) {
+  game
+    .log
+    .add(format!("Your {} dies!", companion.name), colors::DARK_RED);
+  companion.companion = false;
+  leave_corpse(companion);
 }
 
 pub fn use_item(inventory_id: usize, objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) {
-  use Item::*;
   if let Some(item) = game.inventory[inventory_id].item {
-    let on_use = match item {
-      Heal => cast_heal,
-      Lightning => cast_lightning,
-      Confuse => cast_confuse,
-      Fireball => cast_fireball,
-      Sword => toggle_equipment,
-      Shield => toggle_equipment,
+    if game.inventory[inventory_id].charges == Some(0) {
+      game.log.add(
+        format!(
+          "You wave the {}, but nothing happens.",
+          game.inventory[inventory_id].name
+        ),
+        colors::WHITE,
+      );
+      return;
+    }
+    let kind = game.inventory[inventory_id].kind.clone();
+    let result = match item {
+      Item::Heal { amount } => cast_heal(amount, objects, game),
+      Item::Lightning { damage, range } => cast_lightning(damage, range, objects, game, tcod),
+      Item::Confuse { range, turns } => cast_confuse(range, turns, objects, game, tcod),
+      Item::Charm { range } => cast_charm(range, objects, game, tcod),
+      Item::Fireball { damage, radius } => cast_fireball(damage, radius, objects, game, tcod),
+      Item::MagicMapping => cast_magic_mapping(game),
+      Item::Telepathy { turns } => cast_telepathy(turns, objects, game),
+      Item::DetectObjects => cast_detect_objects(objects, game),
+      Item::Regeneration { turns, amount } => cast_regeneration(turns, amount, objects, game),
+      Item::Haste { turns } => cast_haste(turns, objects, game),
+      Item::Strength { turns, bonus } => cast_strength(turns, bonus, objects, game),
+      Item::Blindness { turns } => cast_blindness(turns, objects, game),
+      Item::Slow { range, turns } => cast_slow(range, turns, objects, game, tcod),
+      Item::Paralyse { range, turns } => cast_paralyse(range, turns, objects, game, tcod),
+      Item::Identify => cast_identify(inventory_id, objects, game, tcod),
+      Item::Dig { range } => cast_dig(range, objects, game, tcod),
+      Item::Recharge { charges } => cast_recharge(charges, game, tcod),
+      Item::Equip => toggle_equipment(inventory_id, game),
+      Item::Ammo => ready_ammo(inventory_id, game),
+      Item::Gold | Item::Corpse => cannot_use(inventory_id, game),
+    };
+    // Wands and staves are kept until their charges run out
+    let result = match (result, game.inventory[inventory_id].charges) {
+      (UseResult::UsedUp, Some(charges)) => {
+        game.inventory[inventory_id].charges = Some(charges - 1);
+        game
+          .identification
+          .disguise(&mut game.inventory[inventory_id]);
+        UseResult::UsedAndKept
+      }
+      (result, _) => result,
     };
-    match on_use(inventory_id, objects, game, tcod) {
+    // Using an item gives away what it is
+    if let (Some(kind), false) = (kind, result == UseResult::Cancelled) {
+      identify::identify(&kind, objects, game);
+    }
+    match result {
       UseResult::UsedUp => {
-        game.inventory.remove(inventory_id);
+        let count = game.inventory[inventory_id].count();
+        if count > 1 {
+          game.inventory[inventory_id].set_count(count - 1);
+        } else {
+          game.inventory.remove(inventory_id);
+        }
       }
       UseResult::Cancelled => {
         game.log.add("Cancelled", colors::WHITE);
@@ -411,57 +1521,135 @@ pub fn use_item(inventory_id: usize, objects: &mut [Object], game: &mut Game, tc
   }
 }
 
-fn cast_heal(
-  _inventory_id: usize,
-  objects: &mut [Object],
-  game: &mut Game,
-  _tcod: &mut Tcod,
-) -> UseResult {
-  let player = &mut objects[PLAYER];
-  if let Some(fighter) = player.fighter {
-    if fighter.hp == player.max_hp(game) {
+fn cast_heal(amount: i32, objects: &mut [Object], game: &mut Game) -> UseResult {
+  if let Some(fighter) = objects[PLAYER].fighter {
+    if fighter.hp == objects[PLAYER].max_hp(game) {
       game.log.add("You are already at full health.", colors::RED);
       return UseResult::Cancelled;
     }
-    game
-      .log
-      .add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
-    player.heal(HEAL_AMOUNT, game);
+    spells::heal(PLAYER, amount, objects, game);
     return UseResult::UsedUp;
   }
   UseResult::Cancelled
 }
 
+fn cast_regeneration(
+  turns: i32,
+  amount: i32,
+  objects: &mut [Object],
+  game: &mut Game,
+) -> UseResult {
+  status::apply(&mut objects[PLAYER], Effect::Regeneration, turns, amount);
+  game
+    .log
+    .add("Your wounds begin to knit together.", colors::LIGHT_GREEN);
+  UseResult::UsedUp
+}
+
+fn cast_haste(turns: i32, objects: &mut [Object], game: &mut Game) -> UseResult {
+  status::apply(&mut objects[PLAYER], Effect::Haste, turns, 0);
+  game.log.add(
+    "The world around you seems to slow down.",
+    colors::LIGHT_CYAN,
+  );
+  UseResult::UsedUp
+}
+
+fn cast_strength(turns: i32, bonus: i32, objects: &mut [Object], game: &mut Game) -> UseResult {
+  status::apply(&mut objects[PLAYER], Effect::Strength, turns, bonus);
+  game.log.add(
+    "You feel strong enough to wrestle a troll!",
+    colors::LIGHT_FLAME,
+  );
+  UseResult::UsedUp
+}
+
+fn cast_blindness(turns: i32, objects: &mut [Object], game: &mut Game) -> UseResult {
+  status::apply(&mut objects[PLAYER], Effect::Blindness, turns, 0);
+  game
+    .log
+    .add("A cloud of darkness falls over your eyes!", colors::RED);
+  UseResult::UsedUp
+}
+
+fn cast_slow(
+  range: i32,
+  turns: i32,
+  objects: &mut [Object],
+  game: &mut Game,
+  tcod: &mut Tcod,
+) -> UseResult {
+  game.log.add(
+    "Left-click an enemy to slow it, or right-click to cancel.",
+    colors::LIGHT_CYAN,
+  );
+  match target_monster(tcod, objects, game, Some(range as f32)) {
+    Some(monster_id) => {
+      status::apply(&mut objects[monster_id], Effect::Slow, turns, 0);
+      game.log.add(
+        format!("The {} starts moving sluggishly.", objects[monster_id].name),
+        colors::LIGHT_BLUE,
+      );
+      UseResult::UsedUp
+    }
+    None => UseResult::Cancelled,
+  }
+}
+
+fn cast_paralyse(
+  range: i32,
+  turns: i32,
+  objects: &mut [Object],
+  game: &mut Game,
+  tcod: &mut Tcod,
+) -> UseResult {
+  game.log.add(
+    "Left-click an enemy to paralyse it, or right-click to cancel.",
+    colors::LIGHT_CYAN,
+  );
+  match target_monster(tcod, objects, game, Some(range as f32)) {
+    Some(monster_id) => {
+      status::apply(&mut objects[monster_id], Effect::Paralysis, turns, 0);
+      game.log.add(
+        format!("The {} freezes in place!", objects[monster_id].name),
+        colors::LIGHT_RED,
+      );
+      UseResult::UsedUp
+    }
+    None => UseResult::Cancelled,
+  }
+}
+
 fn cast_lightning(
-  _inventory_id: usize,
+  damage: i32,
+  range: i32,
   objects: &mut [Object],
   game: &mut Game,
   tcod: &mut Tcod,
 ) -> UseResult {
-  let monster_id = closest_monster(LIGHTNING_RANGE, objects, tcod);
-  if let Some(monster_id) = monster_id {
-    game.log.add(
-      format!(
-        "A lightning bolt strikes the {} with a loud thunder! \
-         The damage is {} hit points.",
-        objects[monster_id].name, LIGHTNING_DAMAGE
-      ),
-      colors::LIGHT_BLUE,
-    );
-    if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
-      objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+  game.log.add(
+    "Left-click a target for the lightning bolt, or right-click to cancel.",
+    colors::LIGHT_CYAN,
+  );
+  let target = target_tile(
+    tcod,
+    objects,
+    game,
+    Some(range as f32),
+    Preview::Projectile(range),
+  );
+  match target {
+    Some(target) => {
+      spells::lightning(PLAYER, target, damage, range, objects, game);
+      UseResult::UsedUp
     }
-    UseResult::UsedUp
-  } else {
-    game
-      .log
-      .add("No enemy is close enough to strike.", colors::RED);
-    UseResult::Cancelled
+    None => UseResult::Cancelled,
   }
 }
 
 fn cast_confuse(
-  _inventory_id: usize,
+  range: i32,
+  turns: i32,
   objects: &mut [Object],
   game: &mut Game,
   tcod: &mut Tcod,
@@ -470,20 +1658,9 @@ fn cast_confuse(
     "Left-click an enemy to confuse it, or right-click to cancel.",
     colors::LIGHT_CYAN,
   );
-  let monster_id = target_monster(tcod, objects, game, Some(CONFUSE_RANGE as f32));
+  let monster_id = target_monster(tcod, objects, game, Some(range as f32));
   if let Some(monster_id) = monster_id {
-    let old_ai = objects[monster_id].ai.take().unwrap_or(Ai::Basic);
-    objects[monster_id].ai = Some(Ai::Confused {
-      previous_ai: Box::new(old_ai),
-      num_turns: CONFUSE_NUM_TURNS,
-    });
-    game.log.add(
-      format!(
-        "The eyes of {} look vacant, as it starts to stumble around!",
-        objects[monster_id].name
-      ),
-      colors::LIGHT_GREEN,
-    );
+    spells::confuse(monster_id, turns, objects, game);
     UseResult::UsedUp
   } else {
     game
@@ -493,8 +1670,36 @@ fn cast_confuse(
   }
 }
 
+fn cast_charm(range: i32, objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) -> UseResult {
+  game.log.add(
+    "Left-click an enemy to charm it, or right-click to cancel.",
+    colors::LIGHT_CYAN,
+  );
+  let monster_id = target_monster(tcod, objects, game, Some(range as f32));
+  match monster_id {
+    Some(monster_id) if objects[monster_id].companion => {
+      game.log.add(
+        format!("Your {} is already on your side.", objects[monster_id].name),
+        colors::WHITE,
+      );
+      UseResult::Cancelled
+    }
+    Some(monster_id) => {
+      spells::charm(monster_id, objects, game);
+      UseResult::UsedUp
+    }
+    None => {
+      game
+        .log
+        .add("No enemy is close enough to charm.", colors::RED);
+      UseResult::Cancelled
+    }
+  }
+}
+
 fn cast_fireball(
-  _inventory_id: usize,
+  damage: i32,
+  radius: i32,
   objects: &mut [Object],
   game: &mut Game,
   tcod: &mut Tcod,
@@ -503,46 +1708,198 @@ fn cast_fireball(
     "Left-click a target tile for the fireball, or right-click to cancel.",
     colors::LIGHT_CYAN,
   );
-  let (x, y) = match target_tile(tcod, objects, game, None) {
+  let preview = Preview::Area(radius);
+  let (x, y) = match target_tile(tcod, objects, game, None, preview) {
     Some(tile_pos) => tile_pos,
     None => return UseResult::Cancelled,
   };
-  game.log.add(
-    format!(
-      "The fireball explodes, burning everything within {} tiles!",
-      FIREBALL_RADIUS
-    ),
-    colors::ORANGE,
-  );
 
-  let mut xp_to_gain = 0;
-  for (id, obj) in objects.iter_mut().enumerate() {
-    if obj.distance(x, y) <= FIREBALL_RADIUS as f32 && obj.fighter.is_some() {
-      game.log.add(
-        format!(
-          "The {} gets burned for {} hit points.",
-          obj.name, FIREBALL_DAMAGE
-        ),
-        colors::ORANGE,
-      );
-      if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
-        if id != PLAYER {
-          xp_to_gain += xp;
-        }
+  let area = projectile::blast_area((x, y), radius, &game.map);
+  let friends_in_blast: Vec<_> = objects
+    .iter()
+    .enumerate()
+    .filter(|&(_, obj)| {
+      obj.alive && obj.faction == Some(Faction::Player) && area.contains(&obj.pos())
+    })
+    .map(|(id, obj)| {
+      if id == PLAYER {
+        "you".to_string()
+      } else {
+        format!("your {}", obj.name)
+      }
+    })
+    .collect();
+  if !friends_in_blast.is_empty() {
+    let header = format!(
+      "The fireball will hit {}! Cast it anyway?\n",
+      friends_in_blast.join(" and ")
+    );
+    if menu(&header, &["Yes", "No"], INVENTORY_WIDTH, &mut tcod.root) != Some(0) {
+      return UseResult::Cancelled;
+    }
+  }
+  spells::fireball(PLAYER, (x, y), damage, radius, objects, game);
+  UseResult::UsedUp
+}
+
+fn cast_magic_mapping(game: &mut Game) -> UseResult {
+  // Reveal every floor tile and the walls bordering it, but leave solid rock
+  // unexplored so the map doesn't turn into one big block of wall.
+  for x in 0..MAP_WIDTH {
+    for y in 0..MAP_HEIGHT {
+      let near_floor = (x - 1..x + 2).any(|nx| {
+        (y - 1..y + 2).any(|ny| {
+          nx >= 0
+            && ny >= 0
+            && nx < MAP_WIDTH
+            && ny < MAP_HEIGHT
+            && !game.map[nx as usize][ny as usize].blocked
+        })
+      });
+      if near_floor {
+        game.map[x as usize][y as usize].explored = true;
       }
     }
   }
-  objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
+  game.log.add(
+    "The scroll reveals the layout of this level!",
+    colors::LIGHT_CYAN,
+  );
+  UseResult::UsedUp
+}
 
+fn cast_telepathy(turns: i32, objects: &mut [Object], game: &mut Game) -> UseResult {
+  status::apply(&mut objects[PLAYER], Effect::Telepathy, turns, 0);
+  game.log.add(
+    "You feel a strange mental awareness of the creatures around you.",
+    colors::LIGHT_VIOLET,
+  );
+  UseResult::UsedUp
+}
+
+fn cast_detect_objects(objects: &mut [Object], game: &mut Game) -> UseResult {
+  let mut num_detected = 0;
+  for object in objects.iter_mut().filter(|o| o.item.is_some()) {
+    object.detected = true;
+    num_detected += 1;
+  }
+  if num_detected > 0 {
+    game.log.add(
+      format!(
+        "You sense the presence of {} objects on this level!",
+        num_detected
+      ),
+      colors::LIGHT_CYAN,
+    );
+  } else {
+    game
+      .log
+      .add("You sense no objects on this level.", colors::LIGHT_CYAN);
+  }
   UseResult::UsedUp
 }
 
-fn toggle_equipment(
+fn cast_identify(
   inventory_id: usize,
-  _objects: &mut [Object],
+  objects: &mut [Object],
   game: &mut Game,
-  _tcod: &mut Tcod,
+  tcod: &mut Tcod,
 ) -> UseResult {
+  let unknown: Vec<_> = game
+    .inventory
+    .iter()
+    .enumerate()
+    .filter(|&(id, item)| {
+      id != inventory_id
+        && item
+          .kind
+          .as_ref()
+          .is_some_and(|kind| !game.identification.is_known(kind))
+    })
+    .map(|(id, _)| id)
+    .collect();
+  if unknown.is_empty() {
+    game
+      .log
+      .add("You have nothing that needs identifying.", colors::WHITE);
+    return UseResult::Cancelled;
+  }
+
+  let names: Vec<_> = unknown
+    .iter()
+    .map(|&id| game.inventory[id].name.clone())
+    .collect();
+  let header = "Press the key next to an item to identify it, or any other to cancel.\n";
+  match menu(header, &names, INVENTORY_WIDTH, &mut tcod.root) {
+    Some(index) => {
+      let kind = game.inventory[unknown[index]].kind.clone().unwrap();
+      identify::identify(&kind, objects, game);
+      UseResult::UsedUp
+    }
+    None => UseResult::Cancelled,
+  }
+}
+
+fn cast_dig(range: i32, objects: &mut [Object], game: &mut Game, tcod: &mut Tcod) -> UseResult {
+  game.log.add(
+    "Left-click which way to dig, or right-click to cancel.",
+    colors::LIGHT_CYAN,
+  );
+  let target = match target_tile(tcod, objects, game, None, Preview::Nothing) {
+    Some(target) => target,
+    None => return UseResult::Cancelled,
+  };
+  // The player's own tile doesn't point anywhere
+  if target == objects[PLAYER].pos() {
+    game
+      .log
+      .add("You need to pick a direction to dig in.", colors::WHITE);
+    return UseResult::Cancelled;
+  }
+  let dug = spells::dig(PLAYER, target, range, objects, game);
+  for &(x, y) in &dug {
+    tcod.fov.set(x, y, true, true);
+  }
+  render_all(tcod, objects, game, true);
+  UseResult::UsedUp
+}
+
+fn cast_recharge(charges: i32, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+  let rechargeable: Vec<_> = game
+    .inventory
+    .iter()
+    .enumerate()
+    .filter(|&(_, item)| item.charges.is_some())
+    .map(|(id, _)| id)
+    .collect();
+  if rechargeable.is_empty() {
+    game
+      .log
+      .add("You have nothing that can be recharged.", colors::WHITE);
+    return UseResult::Cancelled;
+  }
+
+  let names: Vec<_> = rechargeable
+    .iter()
+    .map(|&id| game.inventory[id].name.clone())
+    .collect();
+  let header = "Press the key next to an item to recharge it, or any other to cancel.\n";
+  match menu(header, &names, INVENTORY_WIDTH, &mut tcod.root) {
+    Some(index) => {
+      let item = &mut game.inventory[rechargeable[index]];
+      item.charges = item.charges.map(|left| left + charges);
+      game.identification.disguise(item);
+      game.log.add(
+        format!("The {} hums with renewed power.", item.name),
+        colors::LIGHT_VIOLET,
+      );
+      UseResult::UsedUp
+    }
+    None => UseResult::Cancelled,
+  }
+}
+
+fn toggle_equipment(inventory_id: usize, game: &mut Game) -> UseResult {
   let equipment = match game.inventory[inventory_id].equipment {
     Some(equipment) => equipment,
     None => return UseResult::Cancelled,
@@ -550,43 +1907,286 @@ fn toggle_equipment(
   if equipment.equipped {
     game.inventory[inventory_id].dequip(&mut game.log);
   } else {
-    if let Some(old_equipment) = get_equipped_in_slot(equipment.slot, &game.inventory) {
+    // Swap out whatever is in the way in one go
+    find_free_finger(inventory_id, &mut game.inventory);
+    let equipment = game.inventory[inventory_id].equipment.unwrap();
+    for old_equipment in equipped_in_the_way(equipment, &game.inventory) {
       game.inventory[old_equipment].dequip(&mut game.log);
-    } else {
-      game.inventory[inventory_id].equip(&mut game.log);
     }
+    game.inventory[inventory_id].equip(&mut game.log);
+  }
+  UseResult::UsedAndKept
+}
+
+/// Wield a one-handed weapon in the off hand, next to the one in the weapon
+/// hand. Weapons only ever go there when the player asks for it.
+pub fn wield_in_off_hand(inventory_id: usize, game: &mut Game) -> bool {
+  let equipment = match game.inventory[inventory_id].equipment {
+    Some(equipment) if equipment.fits_off_hand() => equipment,
+    _ => {
+      game.log.add(
+        format!(
+          "The {} can't be wielded in your off hand.",
+          game.inventory[inventory_id].name
+        ),
+        colors::WHITE,
+      );
+      return false;
+    }
+  };
+  if equipment.equipped && equipment.slot == Slot::LeftHand {
+    return false;
+  }
+  game.inventory[inventory_id].dequip(&mut game.log);
+  let off_hand = Equipment {
+    slot: Slot::LeftHand,
+    equipped: false,
+    ..equipment
+  };
+  game.inventory[inventory_id].equipment = Some(off_hand);
+  for old_equipment in equipped_in_the_way(off_hand, &game.inventory) {
+    game.inventory[old_equipment].dequip(&mut game.log);
   }
+  game.inventory[inventory_id].equip(&mut game.log);
+  true
+}
+
+fn cannot_use(inventory_id: usize, game: &mut Game) -> UseResult {
+  game.log.add(
+    format!("The {} cannot be used.", game.inventory[inventory_id].name),
+    colors::WHITE,
+  );
   UseResult::UsedAndKept
 }
 
-fn closest_monster(max_range: i32, objects: &mut [Object], tcod: &Tcod) -> Option<usize> {
-  let mut closest_enemy = None;
-  let mut closest_dist = (max_range + 1) as f32;
+fn ready_ammo(inventory_id: usize, game: &mut Game) -> UseResult {
+  game.log.add(
+    format!(
+      "Equip a launcher and press f to fire the {}.",
+      game.inventory[inventory_id].name
+    ),
+    colors::WHITE,
+  );
+  UseResult::UsedAndKept
+}
 
-  for (id, object) in objects.iter().enumerate() {
-    if (id != PLAYER)
-      && object.fighter.is_some()
-      && object.ai.is_some()
-      && tcod.fov.is_in_fov(object.x, object.y)
-    {
-      let dist = objects[PLAYER].distance_to(object);
-      if dist < closest_dist {
-        closest_enemy = Some(id);
-        closest_dist = dist;
+/// Shoot the equipped launcher at a tile the player picks. The shot flies
+/// along a line and hits the first creature in its way, and the ammunition
+/// ends up on the floor wherever it stopped. Returns whether that took a turn.
+pub fn fire(tcod: &mut Tcod, objects: &mut Vec<Object>, game: &mut Game) -> bool {
+  let launcher = game
+    .inventory
+    .iter()
+    .filter(|item| item.equipment.is_some_and(|e| e.equipped))
+    .filter_map(|item| {
+      item
+        .equipment
+        .and_then(|e| e.launcher)
+        .map(|launcher| (item.name.clone(), launcher))
+    })
+    .next();
+  let (launcher_name, launcher) = match launcher {
+    Some(launcher) => launcher,
+    None => {
+      game.log.add("You have nothing to shoot with.", colors::RED);
+      return false;
+    }
+  };
+  let bundle = game
+    .inventory
+    .iter()
+    .position(|item| item.ammo == Some(launcher.ammo));
+  let bundle = match bundle {
+    Some(bundle) => bundle,
+    None => {
+      game.log.add(
+        format!("You have no ammunition for your {}.", launcher_name),
+        colors::RED,
+      );
+      return false;
+    }
+  };
+
+  game.log.add(
+    format!(
+      "Left-click a target for your {}, or right-click to cancel.",
+      launcher_name
+    ),
+    colors::LIGHT_CYAN,
+  );
+  let target = target_tile(
+    tcod,
+    objects,
+    game,
+    Some(launcher.range as f32),
+    Preview::Projectile(launcher.range),
+  );
+  let target = match target {
+    Some(target) => target,
+    None => return false,
+  };
+
+  let shot = if game.inventory[bundle].count() > 1 {
+    game.inventory[bundle].split_off(1)
+  } else {
+    game.inventory.remove(bundle)
+  };
+
+  let from = objects[PLAYER].pos();
+  let (hit, end) = projectile::travel(from, target, launcher.range, &game.map, objects);
+  match hit {
+    Some(target_id) => {
+      let distance = objects[PLAYER].distance_to(&objects[target_id]);
+      let strike = Strike {
+        verb: launcher.ammo.verb(),
+        dice: launcher.damage,
+        damage_type: DamageType::Physical,
+        penalty: projectile::range_penalty(distance),
+      };
+      let (player, target) = mut_two(PLAYER, target_id, objects);
+      let blow = player.hit(target, strike, true, game);
+      settle_blow(PLAYER, target_id, blow, objects, game);
+    }
+    None => game.log.add("Your shot hits nothing.", colors::WHITE),
+  }
+  land(shot, end, objects);
+  true
+}
+
+/// Throw an item from the inventory at a tile the player picks. It flies
+/// like a missile: weapons hurt whatever they hit, potions shatter and
+/// splash everyone close by, and anything else just lands where it stops.
+/// Returns whether that took a turn.
+pub fn throw_item(
+  inventory_id: usize,
+  tcod: &mut Tcod,
+  objects: &mut Vec<Object>,
+  game: &mut Game,
+) -> bool {
+  game.log.add(
+    format!(
+      "Left-click where to throw the {}, or right-click to cancel.",
+      game.inventory[inventory_id].name
+    ),
+    colors::LIGHT_CYAN,
+  );
+  let target = target_tile(
+    tcod,
+    objects,
+    game,
+    Some(THROW_RANGE as f32),
+    Preview::Projectile(THROW_RANGE),
+  );
+  let target = match target {
+    Some(target) => target,
+    None => return false,
+  };
+
+  // Only one item of a stack gets thrown
+  let item = if game.inventory[inventory_id].count() > 1 {
+    game.inventory[inventory_id].split_off(1)
+  } else {
+    take_from_inventory(inventory_id, game)
+  };
+
+  let from = objects[PLAYER].pos();
+  let (hit, end) = projectile::travel(from, target, THROW_RANGE, &game.map, objects);
+  let weapon = item.equipment.and_then(|e| e.damage);
+  match (hit, weapon) {
+    (Some(target_id), Some(dice)) => {
+      let distance = objects[PLAYER].distance_to(&objects[target_id]);
+      let verb = format!("throws the {} at", item.name);
+      let strike = Strike {
+        verb: &verb,
+        dice,
+        damage_type: DamageType::Physical,
+        penalty: projectile::range_penalty(distance),
+      };
+      let (player, target) = mut_two(PLAYER, target_id, objects);
+      let blow = player.hit(target, strike, true, game);
+      settle_blow(PLAYER, target_id, blow, objects, game);
+    }
+    _ => {
+      if item
+        .item
+        .is_some_and(|kind| shatter(kind, end, objects, game))
+      {
+        if let Some(kind) = item.kind {
+          identify::identify(&kind, objects, game);
+        }
+        return true;
+      }
+      if let Some(target_id) = hit {
+        game.log.add(
+          format!(
+            "The {} bounces off the {}.",
+            item.name, objects[target_id].name
+          ),
+          colors::WHITE,
+        );
+      }
+    }
+  }
+
+  land(item, end, objects);
+  true
+}
+
+/// Break a thrown potion at (x, y), giving its effect to every creature
+/// within splashing distance. Returns false if the item isn't a potion.
+fn shatter(item: Item, (x, y): (i32, i32), objects: &mut [Object], game: &mut Game) -> bool {
+  let (effect, turns, magnitude) = match item {
+    Item::Heal { amount } => (None, 0, amount),
+    Item::Regeneration { turns, amount } => (Some(Effect::Regeneration), turns, amount),
+    Item::Haste { turns } => (Some(Effect::Haste), turns, 0),
+    Item::Strength { turns, bonus } => (Some(Effect::Strength), turns, bonus),
+    Item::Blindness { turns } => (Some(Effect::Blindness), turns, 0),
+    Item::Telepathy { turns } => (Some(Effect::Telepathy), turns, 0),
+    _ => return false,
+  };
+
+  game.log.add(
+    "The potion shatters and splashes everything nearby!",
+    colors::LIGHT_BLUE,
+  );
+  // Walls keep the splash off whoever is behind them, as with a fireball
+  let area = projectile::blast_area((x, y), POTION_SPLASH_RADIUS.ceil() as i32, &game.map);
+  let splashed: Vec<_> = objects
+    .iter()
+    .enumerate()
+    .filter(|&(_, object)| {
+      object.alive
+        && object.fighter.is_some()
+        && area.contains(&object.pos())
+        && object.distance(x, y) <= POTION_SPLASH_RADIUS
+    })
+    .map(|(id, _)| id)
+    .collect();
+  for id in splashed {
+    match effect {
+      None => spells::heal(id, magnitude, objects, game),
+      Some(effect) => {
+        status::apply(&mut objects[id], effect, turns, magnitude);
+        let message = if id == PLAYER {
+          format!("You are {}!", effect.adjective())
+        } else {
+          format!("The {} is {}!", objects[id].name, effect.adjective())
+        };
+        game.log.add(message, effect.color());
       }
     }
   }
-  closest_enemy
+  true
 }
 
-fn target_monster(
+pub fn target_monster(
   tcod: &mut Tcod,
   objects: &[Object],
   game: &mut Game,
   max_range: Option<f32>,
 ) -> Option<usize> {
   loop {
-    match target_tile(tcod, objects, game, max_range) {
+    match target_tile(tcod, objects, game, max_range, Preview::Nothing) {
       Some((x, y)) => {
         for (id, obj) in objects.iter().enumerate() {
           if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
@@ -599,11 +2199,22 @@ fn target_monster(
   }
 }
 
+/// What to highlight on the map while the player picks a target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preview {
+  Nothing,
+  /// The path a projectile with this range would take, up to where it stops
+  Projectile(i32),
+  /// The tiles an explosion with this radius would reach
+  Area(i32),
+}
+
 fn target_tile(
   tcod: &mut Tcod,
   objects: &[Object],
   game: &mut Game,
   max_range: Option<f32>,
+  preview: Preview,
 ) -> Option<(i32, i32)> {
   use tcod::input::KeyCode::Escape;
   use tcod::input::{self, Event};
@@ -620,7 +2231,34 @@ fn target_tile(
 
     let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
 
-    let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && tcod.fov.is_in_fov(x, y);
+    let on_map = (x < MAP_WIDTH) && (y < MAP_HEIGHT);
+    let from = objects[PLAYER].pos();
+    let highlighted = match preview {
+      _ if !on_map => vec![],
+      Preview::Nothing => vec![],
+      Preview::Projectile(range) => {
+        let (_, end) = projectile::travel(from, (x, y), range, &game.map, objects);
+        let mut path = line(from, (x, y));
+        let reached = path
+          .iter()
+          .position(|&point| point == end)
+          .map_or(0, |last| last + 1);
+        path.truncate(reached);
+        path
+      }
+      Preview::Area(radius) => projectile::blast_area((x, y), radius, &game.map),
+    };
+    // Only show what the player already knows about, so the preview doesn't
+    // give away the shape of unexplored parts of the map.
+    for &(tile_x, tile_y) in &highlighted {
+      if game.map[tile_x as usize][tile_y as usize].explored {
+        tcod
+          .root
+          .set_char_background(tile_x, tile_y, colors::DARK_YELLOW, BackgroundFlag::Set);
+      }
+    }
+
+    let in_fov = on_map && tcod.fov.is_in_fov(x, y);
     let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
     if tcod.mouse.lbutton_pressed && in_fov && in_range {
       return Some((x, y));
@@ -636,28 +2274,35 @@ fn target_tile(
 pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_recompute: bool) {
   if fov_recompute {
     let player = &objects[PLAYER];
+    let radius = if player.has_effect(Effect::Blindness) {
+      1
+    } else {
+      TORCH_RADIUS
+    };
     tcod
       .fov
-      .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+      .compute_fov(player.x, player.y, radius, FOV_LIGHT_WALLS, FOV_ALGO);
+  }
 
-    for y in 0..MAP_HEIGHT {
-      for x in 0..MAP_WIDTH {
-        let visible = tcod.fov.is_in_fov(x, y);
-        let wall = game.map[x as usize][y as usize].block_sight;
-        let color = match (visible, wall) {
-          (false, true) => COLOR_DARK_WALL,
-          (false, false) => COLOR_DARK_GROUND,
-          (true, true) => COLOR_LIGHT_WALL,
-          (true, false) => COLOR_LIGHT_GROUND,
-        };
-        if visible {
-          game.map[x as usize][y as usize].explored = true;
-        }
-        if game.map[x as usize][y as usize].explored {
-          tcod
-            .con
-            .set_char_background(x, y, color, BackgroundFlag::Set);
-        }
+  // Tiles are redrawn every frame so that magic mapping shows up without
+  // waiting for the player to move.
+  for y in 0..MAP_HEIGHT {
+    for x in 0..MAP_WIDTH {
+      let visible = tcod.fov.is_in_fov(x, y);
+      let wall = game.map[x as usize][y as usize].block_sight;
+      let color = match (visible, wall) {
+        (false, true) => COLOR_DARK_WALL,
+        (false, false) => COLOR_DARK_GROUND,
+        (true, true) => COLOR_LIGHT_WALL,
+        (true, false) => COLOR_LIGHT_GROUND,
+      };
+      if visible {
+        game.map[x as usize][y as usize].explored = true;
+      }
+      if game.map[x as usize][y as usize].explored {
+        tcod
+          .con
+          .set_char_background(x, y, color, BackgroundFlag::Set);
       }
     }
   }
@@ -665,10 +2310,7 @@ pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_reco
   // Sort list of objects so non-blocking objects come first
   let mut to_draw: Vec<_> = objects
     .iter()
-    .filter(|o| {
-      tcod.fov.is_in_fov(o.x, o.y)
-        || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
-    })
+    .filter(|o| is_visible(o, &objects[PLAYER], game, &tcod.fov))
     .collect();
   to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
 
@@ -711,6 +2353,30 @@ pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_reco
     TextAlignment::Left,
     format!("Dungeon level: {}", game.dungeon_level),
   );
+  tcod.panel.print_ex(
+    1,
+    4,
+    BackgroundFlag::None,
+    TextAlignment::Left,
+    format!("Gold: {}", game.gold),
+  );
+
+  // Status effects fill the rest of the side panel
+  for (row, status) in objects[PLAYER]
+    .effects
+    .iter()
+    .take((PANEL_HEIGHT - 5) as usize)
+    .enumerate()
+  {
+    tcod.panel.set_default_foreground(status.effect.color());
+    tcod.panel.print_ex(
+      1,
+      5 + row as i32,
+      BackgroundFlag::None,
+      TextAlignment::Left,
+      format!("{} ({})", status.effect.label(), status.turns_left),
+    );
+  }
 
   tcod.panel.set_default_foreground(colors::LIGHT_GREY);
   tcod.panel.print_ex(
@@ -718,7 +2384,7 @@ pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_reco
     0,
     BackgroundFlag::None,
     TextAlignment::Left,
-    get_names_under_mouse(tcod.mouse, objects, &mut tcod.fov),
+    get_names_under_mouse(tcod.mouse, objects, game, &tcod.fov),
   );
 
   render_messages(&game.log, &mut tcod.panel);
@@ -734,27 +2400,68 @@ pub fn render_all(tcod: &mut Tcod, objects: &[Object], game: &mut Game, fov_reco
   );
 }
 
-fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) -> String {
+/// Whether the player can currently see the object, either directly or
+/// through detection magic.
+fn is_visible(object: &Object, player: &Object, game: &Game, fov_map: &FovMap) -> bool {
+  let explored = game.map[object.x as usize][object.y as usize].explored;
+  let sensed = player.has_effect(Effect::Telepathy) && object.alive && object.ai.is_some();
+  fov_map.is_in_fov(object.x, object.y)
+    || (object.always_visible && explored)
+    || object.detected
+    || sensed
+}
+
+fn get_names_under_mouse(
+  mouse: Mouse,
+  objects: &[Object],
+  game: &Game,
+  fov_map: &FovMap,
+) -> String {
   let (x, y) = (mouse.cx as i32, mouse.cy as i32);
 
   let names = objects
     .iter()
-    .filter(|obj| obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y))
+    .filter(|obj| obj.pos() == (x, y) && is_visible(obj, &objects[PLAYER], game, fov_map))
     .map(|obj| obj.name.clone())
     .collect::<Vec<_>>();
 
   names.join(", ")
 }
 
-fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
-  for (inventory_id, item) in inventory.iter().enumerate() {
-    if item
-      .equipment
-      .as_ref()
-      .map_or(false, |e| e.equipped && e.slot == slot)
-    {
-      return Some(inventory_id);
-    }
+/// Rings fit on either hand, so put one on the other finger if that's free
+/// and the one it was last worn on isn't.
+fn find_free_finger(inventory_id: usize, inventory: &mut [Object]) {
+  let equipment = match inventory[inventory_id].equipment {
+    Some(equipment) => equipment,
+    None => return,
+  };
+  let other_finger = match equipment.slot.other_finger() {
+    Some(slot) => slot,
+    None => return,
+  };
+  let moved = Equipment {
+    slot: other_finger,
+    ..equipment
+  };
+  if !equipped_in_the_way(equipment, inventory).is_empty()
+    && equipped_in_the_way(moved, inventory).is_empty()
+  {
+    inventory[inventory_id].equipment = Some(moved);
   }
-  None
+}
+
+/// The equipped items that take up any of the slots `equipment` needs, such
+/// as both weapons when picking up a two-handed sword.
+fn equipped_in_the_way(equipment: Equipment, inventory: &[Object]) -> Vec<usize> {
+  let needed = equipment.slots();
+  inventory
+    .iter()
+    .enumerate()
+    .filter(|&(_, item)| {
+      item
+        .equipment
+        .is_some_and(|e| e.equipped && e.slots().iter().any(|slot| needed.contains(slot)))
+    })
+    .map(|(inventory_id, _)| inventory_id)
+    .collect()
 }
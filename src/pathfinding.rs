@@ -0,0 +1,99 @@
+use constants::*;
+use object::*;
+use std::cmp::{self, Reverse};
+use std::collections::BinaryHeap;
+
+/// Cost of stepping onto a tile, or `None` if it can't be entered at all.
+/// Tiles holding a blocking object are passable but expensive, so paths go
+/// around other creatures when there's room and queue up behind them when
+/// there isn't.
+pub fn movement_cost(x: i32, y: i32, map: &Map, objects: &[Object]) -> Option<i32> {
+  if map[x as usize][y as usize].blocked {
+    return None;
+  }
+  let occupied = objects
+    .iter()
+    .any(|object| object.blocks && object.pos() == (x, y));
+  if occupied {
+    Some(OCCUPIED_TILE_PATH_COST)
+  } else {
+    Some(1)
+  }
+}
+
+/// Find the cheapest path from `start` to `goal` with A*, moving in all eight
+/// directions. `cost` gives the price of entering a tile; the goal itself can
+/// always be entered, so a creature standing on it doesn't hide it.
+/// The returned path excludes `start` and ends with `goal`.
+pub fn find_path<F>(start: (i32, i32), goal: (i32, i32), cost: F) -> Option<Vec<(i32, i32)>>
+where
+  F: Fn(i32, i32) -> Option<i32>,
+{
+  let index = |(x, y): (i32, i32)| (x * MAP_HEIGHT + y) as usize;
+  let size = (MAP_WIDTH * MAP_HEIGHT) as usize;
+
+  let mut best_cost = vec![i32::MAX; size];
+  let mut came_from: Vec<Option<(i32, i32)>> = vec![None; size];
+  let mut open = BinaryHeap::new();
+
+  best_cost[index(start)] = 0;
+  open.push(Reverse((heuristic(start, goal), 0, start)));
+
+  while let Some(Reverse((_, cost_so_far, pos))) = open.pop() {
+    if pos == goal {
+      let mut path = vec![goal];
+      let mut current = goal;
+      while let Some(previous) = came_from[index(current)] {
+        if previous == start {
+          break;
+        }
+        path.push(previous);
+        current = previous;
+      }
+      path.reverse();
+      return Some(path);
+    }
+    if cost_so_far > best_cost[index(pos)] {
+      // A cheaper route to this tile was already expanded
+      continue;
+    }
+
+    for dx in -1..2 {
+      for dy in -1..2 {
+        let next = (pos.0 + dx, pos.1 + dy);
+        if (dx, dy) == (0, 0)
+          || next.0 < 0
+          || next.1 < 0
+          || next.0 >= MAP_WIDTH
+          || next.1 >= MAP_HEIGHT
+        {
+          continue;
+        }
+        let step_cost = if next == goal {
+          Some(1)
+        } else {
+          cost(next.0, next.1)
+        };
+        if let Some(step_cost) = step_cost {
+          let next_cost = cost_so_far + step_cost;
+          if next_cost < best_cost[index(next)] {
+            best_cost[index(next)] = next_cost;
+            came_from[index(next)] = Some(pos);
+            open.push(Reverse((
+              next_cost + heuristic(next, goal),
+              next_cost,
+              next,
+            )));
+          }
+        }
+      }
+    }
+  }
+  None
+}
+
+/// Diagonal moves cost the same as straight ones, so the number of moves
+/// between two tiles is their Chebyshev distance.
+fn heuristic(from: (i32, i32), to: (i32, i32)) -> i32 {
+  cmp::max((from.0 - to.0).abs(), (from.1 - to.1).abs())
+}
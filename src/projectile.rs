@@ -0,0 +1,92 @@
+use constants::*;
+use object::*;
+use std::cmp;
+use std::collections::VecDeque;
+use utils::line;
+
+/// Trace a projectile from `from` towards `to` and return the first creature
+/// it hits. It stops at the target tile, after `max_range` tiles, or when it
+/// runs into a wall.
+pub fn fly(
+  from: (i32, i32),
+  to: (i32, i32),
+  max_range: i32,
+  map: &Map,
+  objects: &[Object],
+) -> Option<usize> {
+  travel(from, to, max_range, map, objects).0
+}
+
+/// Like `fly`, but also returns the tile where the projectile came to rest:
+/// the creature it hit, or the last open tile before it stopped.
+pub fn travel(
+  from: (i32, i32),
+  to: (i32, i32),
+  max_range: i32,
+  map: &Map,
+  objects: &[Object],
+) -> (Option<usize>, (i32, i32)) {
+  let mut end = from;
+  for (x, y) in line(from, to).into_iter().take(max_range as usize) {
+    if map[x as usize][y as usize].blocked {
+      return (None, end);
+    }
+    end = (x, y);
+    let hit = objects
+      .iter()
+      .position(|object| object.pos() == (x, y) && object.blocks && object.fighter.is_some());
+    if hit.is_some() {
+      return (hit, end);
+    }
+  }
+  (None, end)
+}
+
+/// Whether a projectile fired from `from` would reach the target, rather than
+/// hitting a wall or another creature first.
+pub fn has_line_of_fire(
+  from: (i32, i32),
+  target_id: usize,
+  max_range: i32,
+  map: &Map,
+  objects: &[Object],
+) -> bool {
+  fly(from, objects[target_id].pos(), max_range, map, objects) == Some(target_id)
+}
+
+/// How much a shot from `distance` away takes off the chance to hit.
+pub fn range_penalty(distance: f32) -> i32 {
+  cmp::max(distance as i32 - 1, 0) * RANGE_PENALTY_PER_TILE
+}
+
+/// The tiles an explosion at `centre` reaches. It spreads outwards through
+/// open tiles only, so walls shelter whatever is behind them.
+pub fn blast_area(centre: (i32, i32), radius: i32, map: &Map) -> Vec<(i32, i32)> {
+  let open = |(x, y): (i32, i32)| {
+    x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT && !map[x as usize][y as usize].blocked
+  };
+  let in_radius = |(x, y): (i32, i32)| {
+    let (dx, dy) = (x - centre.0, y - centre.1);
+    ((dx * dx + dy * dy) as f32).sqrt() <= radius as f32
+  };
+
+  let mut area = vec![];
+  if !open(centre) {
+    return area;
+  }
+  let mut frontier = VecDeque::new();
+  area.push(centre);
+  frontier.push_back(centre);
+  while let Some((x, y)) = frontier.pop_front() {
+    for dx in -1..2 {
+      for dy in -1..2 {
+        let next = (x + dx, y + dy);
+        if open(next) && in_radius(next) && !area.contains(&next) {
+          area.push(next);
+          frontier.push_back(next);
+        }
+      }
+    }
+  }
+  area
+}
@@ -0,0 +1,63 @@
+use rand::{self, Rng, SeedableRng, StdRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The game's own random number generator, so that one seed always plays
+/// out the same way. The generator itself can't be saved, so a save keeps
+/// the seed and how many numbers have been drawn, and loading winds a fresh
+/// generator forward to the same point.
+pub struct GameRng {
+  seed: usize,
+  draws: u64,
+  rng: StdRng,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedRng {
+  seed: usize,
+  draws: u64,
+}
+
+impl GameRng {
+  pub fn new(seed: usize) -> Self {
+    GameRng {
+      seed,
+      draws: 0,
+      rng: StdRng::from_seed(&[seed][..]),
+    }
+  }
+}
+
+impl Default for GameRng {
+  /// A generator with a seed of its own.
+  fn default() -> Self {
+    GameRng::new(rand::thread_rng().gen())
+  }
+}
+
+impl Rng for GameRng {
+  fn next_u32(&mut self) -> u32 {
+    self.draws += 1;
+    self.rng.next_u32()
+  }
+}
+
+impl Serialize for GameRng {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    SavedRng {
+      seed: self.seed,
+      draws: self.draws,
+    }
+    .serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for GameRng {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let saved = SavedRng::deserialize(deserializer)?;
+    let mut rng = GameRng::new(saved.seed);
+    for _ in 0..saved.draws {
+      rng.next_u32();
+    }
+    Ok(rng)
+  }
+}
@@ -0,0 +1,205 @@
+use constants::*;
+use faction::Faction;
+use gui::*;
+use object::*;
+use projectile;
+use status::{self, Effect};
+use tcod::colors;
+use utils::line;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Spell {
+  Heal,
+  Lightning,
+  Confuse,
+  Fireball,
+}
+
+/// A spell a monster can cast, and how long until it can cast it again.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KnownSpell {
+  pub spell: Spell,
+  pub cooldown: i32,
+  pub turns_left: i32,
+}
+
+impl KnownSpell {
+  pub fn new(spell: Spell, cooldown: i32) -> Self {
+    KnownSpell {
+      spell,
+      cooldown,
+      turns_left: 0,
+    }
+  }
+}
+
+// The effects below don't care whether they come from a scroll the player
+// read or a monster's spell; choosing the target is up to the caller.
+
+pub fn heal(target_id: usize, amount: i32, objects: &mut [Object], game: &mut Game) {
+  if target_id == PLAYER {
+    game
+      .log
+      .add("Your wounds start to feel better!", colors::LIGHT_VIOLET);
+  } else {
+    game.log.add(
+      format!("The {} looks healthier.", objects[target_id].name),
+      colors::LIGHT_VIOLET,
+    );
+  }
+  objects[target_id].heal(amount, game);
+}
+
+/// Send a lightning bolt from the caster towards `target`. It strikes the
+/// first creature in its way, which isn't necessarily the one aimed at.
+pub fn lightning(
+  caster_id: usize,
+  target: (i32, i32),
+  damage: i32,
+  range: i32,
+  objects: &mut [Object],
+  game: &mut Game,
+) {
+  let from = objects[caster_id].pos();
+  let struck = match projectile::fly(from, target, range, &game.map, objects) {
+    Some(struck) => struck,
+    None => {
+      game.log.add(
+        "The lightning bolt crackles and fizzles out.",
+        colors::LIGHT_BLUE,
+      );
+      return;
+    }
+  };
+  let damage = objects[struck].resist(damage, DamageType::Lightning, game);
+  game.log.add(
+    format!(
+      "A lightning bolt strikes the {} with a loud thunder! \
+       The damage is {} hit points.",
+      objects[struck].name, damage
+    ),
+    colors::LIGHT_BLUE,
+  );
+  game.record_damage(caster_id, struck, damage);
+  if let Some(xp) = objects[struck].take_damage(damage, game) {
+    if struck != caster_id {
+      gain_xp(caster_id, xp, objects, game);
+    }
+  }
+}
+
+pub fn confuse(target_id: usize, num_turns: i32, objects: &mut [Object], game: &mut Game) {
+  status::apply(&mut objects[target_id], Effect::Confusion, num_turns, 0);
+  if target_id == PLAYER {
+    game
+      .log
+      .add("You feel dizzy and start to stumble around!", colors::RED);
+  } else {
+    game.log.add(
+      format!(
+        "The eyes of {} look vacant, as it starts to stumble around!",
+        objects[target_id].name
+      ),
+      colors::LIGHT_GREEN,
+    );
+  }
+}
+
+/// Win a monster over for good: it leaves its pack and its faction, and
+/// follows the player from now on.
+pub fn charm(target_id: usize, objects: &mut [Object], game: &mut Game) {
+  let target = &mut objects[target_id];
+  target.faction = Some(Faction::Player);
+  target.companion = true;
+  target.pack = None;
+  target.last_seen_player = None;
+  target.ai = Some(Ai::Companion {
+    order: Order::Follow,
+  });
+  if let Some(fighter) = target.fighter.as_mut() {
+    fighter.on_death = DeathCallback::Companion;
+  }
+  game.log.add(
+    format!(
+      "The {} looks at you adoringly and joins your side!",
+      target.name
+    ),
+    colors::LIGHT_GREEN,
+  );
+}
+
+pub fn fireball(
+  caster_id: usize,
+  centre: (i32, i32),
+  damage: i32,
+  radius: i32,
+  objects: &mut [Object],
+  game: &mut Game,
+) {
+  game.log.add(
+    format!(
+      "The fireball explodes, burning everything within {} tiles!",
+      radius
+    ),
+    colors::ORANGE,
+  );
+
+  let area = projectile::blast_area(centre, radius, &game.map);
+  let mut xp_to_gain = 0;
+  for (id, obj) in objects.iter_mut().enumerate() {
+    if area.contains(&obj.pos()) && obj.fighter.is_some() {
+      let damage = obj.resist(damage, DamageType::Fire, game);
+      game.log.add(
+        format!("The {} gets burned for {} hit points.", obj.name, damage),
+        colors::ORANGE,
+      );
+      game.record_damage(caster_id, id, damage);
+      if let Some(xp) = obj.take_damage(damage, game) {
+        if id != caster_id {
+          xp_to_gain += xp;
+        }
+      }
+    }
+  }
+  gain_xp(caster_id, xp_to_gain, objects, game);
+}
+
+/// Bore a tunnel from the caster through up to `range` tiles of rock, in
+/// the direction of `target`. The outer wall of the level is too hard to
+/// dig through. Returns the tiles that were dug out.
+pub fn dig(
+  caster_id: usize,
+  target: (i32, i32),
+  range: i32,
+  objects: &[Object],
+  game: &mut Game,
+) -> Vec<(i32, i32)> {
+  let from = objects[caster_id].pos();
+  let far = (
+    from.0 + (target.0 - from.0) * range,
+    from.1 + (target.1 - from.1) * range,
+  );
+  let mut dug = vec![];
+  for &(x, y) in line(from, far).iter().take(range as usize) {
+    if x <= 0 || y <= 0 || x >= MAP_WIDTH - 1 || y >= MAP_HEIGHT - 1 {
+      break;
+    }
+    let tile = &mut game.map[x as usize][y as usize];
+    if tile.blocked {
+      tile.blocked = false;
+      tile.block_sight = false;
+      dug.push((x, y));
+    }
+  }
+  if dug.is_empty() {
+    game
+      .log
+      .add("There's no rock there to dig through.", colors::WHITE);
+  } else {
+    game.log.add(
+      "The rock crumbles away, leaving a tunnel behind.",
+      colors::LIGHT_SEPIA,
+    );
+  }
+  dug
+}
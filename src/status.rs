@@ -0,0 +1,180 @@
+use constants::*;
+use gui::*;
+use object::*;
+use std::cmp;
+use tcod::colors::{self, Color};
+use tcod::map::Map as FovMap;
+
+/// Temporary conditions that any creature, the player included, can be
+/// under. Most just change how the creature acts while they last; poison and
+/// regeneration also do something every turn.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Effect {
+  Poison,
+  Regeneration,
+  Haste,
+  Slow,
+  Paralysis,
+  Blindness,
+  Confusion,
+  Strength,
+  Telepathy,
+}
+
+impl Effect {
+  /// As in "you are no longer confused".
+  pub fn adjective(self) -> &'static str {
+    match self {
+      Effect::Poison => "poisoned",
+      Effect::Regeneration => "regenerating",
+      Effect::Haste => "hasted",
+      Effect::Slow => "slowed",
+      Effect::Paralysis => "paralysed",
+      Effect::Blindness => "blind",
+      Effect::Confusion => "confused",
+      Effect::Strength => "unusually strong",
+      Effect::Telepathy => "telepathic",
+    }
+  }
+
+  /// Short name for the side panel.
+  pub fn label(self) -> &'static str {
+    match self {
+      Effect::Poison => "Poisoned",
+      Effect::Regeneration => "Regenerating",
+      Effect::Haste => "Hasted",
+      Effect::Slow => "Slowed",
+      Effect::Paralysis => "Paralysed",
+      Effect::Blindness => "Blind",
+      Effect::Confusion => "Confused",
+      Effect::Strength => "Strong",
+      Effect::Telepathy => "Telepathic",
+    }
+  }
+
+  pub fn color(self) -> Color {
+    match self {
+      Effect::Poison => colors::CHARTREUSE,
+      Effect::Regeneration => colors::LIGHT_GREEN,
+      Effect::Haste => colors::LIGHT_CYAN,
+      Effect::Slow => colors::LIGHT_BLUE,
+      Effect::Paralysis => colors::LIGHT_RED,
+      Effect::Blindness => colors::GREY,
+      Effect::Confusion => colors::LIGHT_ORANGE,
+      Effect::Strength => colors::LIGHT_FLAME,
+      Effect::Telepathy => colors::LIGHT_VIOLET,
+    }
+  }
+
+  /// Effects that cancel each other out instead of both applying.
+  fn opposite(self) -> Option<Effect> {
+    match self {
+      Effect::Haste => Some(Effect::Slow),
+      Effect::Slow => Some(Effect::Haste),
+      _ => None,
+    }
+  }
+
+  /// Whether another dose makes the effect stronger. Everything else only
+  /// has its duration topped up.
+  fn intensifies(self) -> bool {
+    matches!(self, Effect::Poison | Effect::Strength)
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+  pub effect: Effect,
+  pub turns_left: i32,
+  /// Damage or healing per turn, or the size of a bonus
+  pub magnitude: i32,
+}
+
+/// Put the creature under an effect, following the stacking rules: an
+/// opposite effect is cancelled instead, intensifying effects add up, and
+/// the longer of the two durations is kept.
+pub fn apply(object: &mut Object, effect: Effect, turns: i32, magnitude: i32) {
+  if let Some(opposite) = effect.opposite() {
+    if object.has_effect(opposite) {
+      object.effects.retain(|status| status.effect != opposite);
+      return;
+    }
+  }
+  match object
+    .effects
+    .iter_mut()
+    .find(|status| status.effect == effect)
+  {
+    Some(status) => {
+      status.turns_left = cmp::max(status.turns_left, turns);
+      if effect.intensifies() {
+        status.magnitude += magnitude;
+      } else {
+        status.magnitude = cmp::max(status.magnitude, magnitude);
+      }
+    }
+    None => object.effects.push(StatusEffect {
+      effect,
+      turns_left: turns,
+      magnitude,
+    }),
+  }
+}
+
+/// Run everyone's effects for one turn: poison and regeneration do their
+/// work, durations count down, and effects that wear off are reported for
+/// the player and the creatures in view.
+pub fn tick(objects: &mut [Object], game: &mut Game, fov_map: &FovMap) {
+  for (id, object) in objects.iter_mut().enumerate() {
+    if !object.alive {
+      object.effects.clear();
+      continue;
+    }
+
+    for status in object.effects.clone() {
+      match status.effect {
+        Effect::Poison => {
+          let damage = object.resist(status.magnitude, DamageType::Poison, game);
+          if id == PLAYER && damage > 0 {
+            game.log.add(
+              format!("The poison burns for {} hit points.", damage),
+              colors::CHARTREUSE,
+            );
+            game.level_stats().taken += damage;
+          }
+          object.take_damage(damage, game);
+        }
+        Effect::Regeneration => object.heal(status.magnitude, game),
+        _ => {}
+      }
+    }
+    if !object.alive {
+      object.effects.clear();
+      continue;
+    }
+
+    for status in object.effects.iter_mut() {
+      status.turns_left -= 1;
+    }
+    let expired: Vec<_> = object
+      .effects
+      .iter()
+      .filter(|status| status.turns_left <= 0)
+      .map(|status| status.effect)
+      .collect();
+    object.effects.retain(|status| status.turns_left > 0);
+    if id != PLAYER && !fov_map.is_in_fov(object.x, object.y) {
+      continue;
+    }
+    for effect in expired {
+      let message = match (id, effect) {
+        (PLAYER, Effect::Telepathy) => {
+          "Your mental awareness of other creatures fades.".to_string()
+        }
+        (PLAYER, _) => format!("You are no longer {}.", effect.adjective()),
+        _ => format!("The {} is no longer {}.", object.name, effect.adjective()),
+      };
+      game.log.add(message, colors::WHITE);
+    }
+  }
+}
@@ -1,5 +1,23 @@
 use std::cmp;
 
+/// A value that changes with depth: it holds from `level` down, until the
+/// next transition in the table.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+  pub level: u32,
+  pub value: u32,
+}
+
+/// The value a table of transitions gives on this dungeon level, or 0 if it
+/// doesn't start until deeper down.
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+  table
+    .iter()
+    .rev()
+    .find(|transition| level >= transition.level)
+    .map_or(0, |transition| transition.value)
+}
+
 /// Mutably borrow two *separate* elements from the given slice.
 /// Panics when the indexes are equal or out of bounds.
 pub fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
@@ -13,3 +31,41 @@ pub fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (
     (&mut second_slice[0], &mut first_slice[second_index])
   }
 }
+
+/// The tiles on a straight line from `from` to `to` (Bresenham's algorithm),
+/// not including `from` but including `to`.
+pub fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+  let (mut x, mut y) = from;
+  let dx = (to.0 - x).abs();
+  let dy = -(to.1 - y).abs();
+  let step_x = if x < to.0 { 1 } else { -1 };
+  let step_y = if y < to.1 { 1 } else { -1 };
+  let mut error = dx + dy;
+  let mut points = vec![];
+  while (x, y) != to {
+    let doubled = 2 * error;
+    if doubled >= dy {
+      error += dy;
+      x += step_x;
+    }
+    if doubled <= dx {
+      error += dx;
+      y += step_y;
+    }
+    points.push((x, y));
+  }
+  points
+}
+
+/// The plural of an item name, as in "healing potions", "scrolls of
+/// fireball" or "scrolls labelled XYZZY".
+pub fn plural(name: &str) -> String {
+  match name.find(" of ").or_else(|| name.find(" labelled ")) {
+    Some(index) => format!("{}{}", plural(&name[..index]), &name[index..]),
+    None if name.ends_with('s') || name.ends_with('x') || name.ends_with("ch") => {
+      format!("{}es", name)
+    }
+    None if name.ends_with("staff") => format!("{}ves", &name[..name.len() - 2]),
+    None => format!("{}s", name),
+  }
+}